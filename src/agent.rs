@@ -0,0 +1,790 @@
+use async_openai::config::OpenAIConfig;
+use async_openai::error::{ApiError, OpenAIError};
+use async_openai::types::{CreateChatCompletionRequest, CreateChatCompletionResponse, Role, Stop};
+use async_openai::Client;
+use eyre::{Context, Result};
+use sqlx::sqlite::SqliteConnection;
+use sqlx::{Column, Executor};
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+use crate::error::{ErrorKind, WithKind};
+use crate::interrupt::{self, OnCtrlC};
+use crate::render::{self, Format, RenderOptions, ResultSet};
+use crate::{cache, db, log, prompt, sql, Args};
+
+/// The candidate queries generated for a question, and the result of running each one.
+pub struct Candidates {
+    pub prompt: Vec<(Role, String)>,
+
+    /// Raw text of each completion.
+    pub responses: Vec<String>,
+
+    /// SQL extracted from each completion, as it's run.
+    pub sql: Vec<String>,
+
+    /// The SQL in `sql` before `--with-instructor`, `--first`, or `--count` wrapped it. This is
+    /// what's cached and saved, since another run wraps it again as it asks.
+    pub model_sql: Vec<String>,
+
+    pub results: Vec<Result<ResultSet>>,
+
+    /// With `--clarify`, a question the model asked instead of writing SQL. There are no
+    /// candidates when this is set.
+    pub clarification: Option<String>,
+
+    /// Whether Ctrl-C stopped fetching rows. The last result has the rows fetched so far, and
+    /// any later candidates weren't run.
+    pub interrupted: bool,
+}
+
+impl Candidates {
+    pub fn log_record<'a>(
+        &'a self,
+        model: &'a str,
+        chosen: Option<(usize, Option<usize>)>,
+    ) -> log::LogRecord<'a> {
+        log::LogRecord {
+            model,
+            prompt: &self.prompt,
+            responses: &self.responses,
+            sql: chosen.map(|(i, _)| self.sql[i].as_str()),
+            row_count: chosen.and_then(|(_, row_count)| row_count),
+        }
+    }
+}
+
+/// The chosen query for a question, and its results.
+pub struct Answer {
+    pub sql: String,
+
+    /// `sql` before it was wrapped, as in `Candidates::model_sql`.
+    pub model_sql: String,
+
+    pub results: ResultSet,
+
+    /// Whether the results are partial, because Ctrl-C stopped fetching them.
+    pub interrupted: bool,
+}
+
+/// Ask the model for SQL to answer `question`, and run each candidate it returns. The model is
+/// skipped if the SQL is given with `--sql` or found in `--cache-file`.
+pub async fn run_candidates(
+    args: &Args,
+    conn: &mut SqliteConnection,
+    oai_client: &Client<OpenAIConfig>,
+    question: &str,
+) -> Result<Candidates> {
+    let cached = match &args.cache_file {
+        Some(path) if args.sql.is_none() => cache::lookup(path, question)?,
+        _ => None,
+    };
+
+    let (prompt, responses) = match args.sql.clone().or(cached) {
+        Some(sql) => (vec![], vec![sql]),
+        None if args.offline => {
+            return Err(eyre::eyre!(
+                "No SQL for this question in offline mode; pass --sql, or a --cache-file that has an entry for it"
+            ))
+            .kind(ErrorKind::Usage);
+        }
+        None => ask_model(args, conn, oai_client, question).await?,
+    };
+
+    // With `--clarify`, the model answers with `?` and a question when it can't tell what the
+    // student means.
+    if args.clarify {
+        let clarification = responses.iter().find_map(|text| {
+            let text = sql::extract_sql(text);
+            let question = text.trim().strip_prefix('?')?;
+            Some(question.trim().to_string())
+        });
+        if clarification.is_some() {
+            return Ok(Candidates {
+                prompt,
+                responses,
+                sql: vec![],
+                model_sql: vec![],
+                results: vec![],
+                clarification,
+                interrupted: false,
+            });
+        }
+    }
+
+    // Get the query from each candidate's response text. Writes skip the SELECT-only guard.
+    let candidates: Vec<String> = responses
+        .iter()
+        .map(|text| {
+            let sql = sql::extract_sql(text);
+            if args.insecure_allow_writes {
+                Ok(sql.trim().to_string())
+            } else {
+                sql::single_statement(&sql)
+            }
+        })
+        .collect::<Result<_>>()
+        .kind(ErrorKind::Sql)?;
+
+    if candidates.is_empty() {
+        return Err(eyre::eyre!("OpenAI returned no candidate queries")).kind(ErrorKind::Api);
+    }
+    let mut model_sql = candidates.clone();
+
+    let candidates = if args.with_instructor {
+        let mut wrapped = vec![];
+        for sql in candidates {
+            wrapped.push(add_instructor(conn, sql).await);
+        }
+        wrapped
+    } else {
+        candidates
+    };
+
+    let candidates: Vec<String> = match args.first {
+        Some(n) => candidates.iter().map(|sql| sql::first(sql, n)).collect(),
+        None => candidates,
+    };
+
+    // Counted rows never reach `prepare_results`, so online sections are left out in SQL.
+    let candidates = if args.count {
+        let mut counted = vec![];
+        for sql in candidates {
+            let sql = if args.include_online {
+                sql
+            } else {
+                exclude_online(conn, sql).await
+            };
+            counted.push(sql::count(&sql));
+        }
+        counted
+    } else {
+        candidates
+    };
+
+    if args.dry_run {
+        // What's printed isn't run, so check its names here.
+        db::check_identifiers(conn, &candidates[0])
+            .await
+            .kind(ErrorKind::Sql)?;
+        return Ok(Candidates {
+            prompt,
+            responses,
+            sql: candidates,
+            model_sql,
+            results: vec![],
+            clarification: None,
+            interrupted: false,
+        });
+    }
+
+    // Run every candidate.
+    let mut results = vec![];
+    let mut interrupted = false;
+    for (i, sql) in candidates.iter().enumerate() {
+        if args.verbose {
+            if candidates.len() > 1 {
+                eprintln!("-- Candidate {}", i + 1);
+            }
+            let shown = if args.pretty_sql {
+                sql::pretty(sql)
+            } else {
+                sql.clone()
+            };
+            match args.truncate_sql {
+                Some(max_chars) => eprintln!("{}", sql::truncate(&shown, max_chars)),
+                None => eprintln!("{shown}"),
+            }
+        }
+
+        // Catch made-up table and column names before running anything.
+        if let Err(err) = db::check_identifiers(conn, sql).await {
+            results.push(Err(err).kind(ErrorKind::Sql));
+            continue;
+        }
+
+        let result = fetch_interruptibly(conn, sql)
+            .await
+            .wrap_err("Failed to execute SQL query")
+            .kind(ErrorKind::Sql);
+        match result {
+            Ok((rows, stopped)) => {
+                results.push(Ok(rows));
+                if stopped {
+                    interrupted = true;
+                    break;
+                }
+            }
+            Err(err) => results.push(Err(err)),
+        }
+    }
+
+    // Only the candidates that ran are kept.
+    let mut candidates = candidates;
+    candidates.truncate(results.len());
+    model_sql.truncate(results.len());
+
+    Ok(Candidates {
+        prompt,
+        responses,
+        sql: candidates,
+        model_sql,
+        results,
+        clarification: None,
+        interrupted,
+    })
+}
+
+/// Fetch the results of `sql`, stopping early if the user presses Ctrl-C. Returns the rows
+/// fetched so far, and whether it was stopped.
+async fn fetch_interruptibly(conn: &mut SqliteConnection, sql: &str) -> Result<(ResultSet, bool)> {
+    let columns = db::column_names(conn, sql).await?;
+    let mut rows = vec![];
+    let mut stream = conn.fetch(sql);
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    // The handler from `interrupt::install` puts Ctrl-C back to exiting once it's been pressed.
+    interrupt::set(OnCtrlC::Stop);
+    loop {
+        tokio::select! {
+            row = stream.next() => match row {
+                Some(row) => rows.push(row.inspect_err(|_| interrupt::set(OnCtrlC::Exit))?),
+                None => break,
+            },
+            _ = &mut ctrl_c => return Ok((ResultSet::from_rows(columns, &rows)?, true)),
+        }
+    }
+    interrupt::set(OnCtrlC::Exit);
+    Ok((ResultSet::from_rows(columns, &rows)?, false))
+}
+
+/// Build the prompt for `question` and ask the model for SQL, returning the prompt and the raw
+/// text of each completion.
+async fn ask_model(
+    args: &Args,
+    conn: &mut SqliteConnection,
+    oai_client: &Client<OpenAIConfig>,
+    question: &str,
+) -> Result<(Vec<(Role, String)>, Vec<String>)> {
+    let prompt = match &args.replay_prompt {
+        Some(path) => prompt::load(path).kind(ErrorKind::Usage)?,
+        None => assemble_prompt(args, conn, question).await?,
+    };
+    if let Some(path) = &args.dump_prompt {
+        prompt::save(path, &prompt)?;
+    }
+
+    // The API takes at most 4 stop sequences.
+    let stop: Vec<String> = args
+        .stop
+        .iter()
+        .filter(|s| !s.is_empty())
+        .cloned()
+        .collect();
+    if stop.len() > 4 {
+        return Err(eyre::eyre!("--stop can be given at most 4 times")).kind(ErrorKind::Usage);
+    }
+    let stop = (!stop.is_empty()).then_some(Stop::StringArray(stop));
+
+    // Build the OpenAI request.
+    let mut chat_completion_request = CreateChatCompletionRequest {
+        model: args.model.to_string(),
+        messages: prompt
+            .iter()
+            .cloned()
+            .map(
+                |(role, content)| async_openai::types::ChatCompletionRequestMessage {
+                    role,
+                    content: Some(content),
+                    ..Default::default()
+                },
+            )
+            .collect(),
+        n: Some(args.num_completions),
+        top_p: args.top_p,
+        presence_penalty: args.presence_penalty,
+        frequency_penalty: args.frequency_penalty,
+        stop,
+        ..Default::default()
+    };
+
+    let response = create_completion(args, oai_client, chat_completion_request.clone())
+        .await
+        .wrap_err("Failed to open result stream from OpenAI")
+        .kind(ErrorKind::Api)?;
+
+    let mut responses: Vec<String> = response
+        .choices
+        .into_iter()
+        .filter_map(|choice| choice.message.content)
+        .collect();
+
+    // A stop sequence for the closing code fence also matches the opening one, so a model that
+    // writes prose before its code block stops there, with no SQL. Ask again without stop
+    // sequences; extracting the SQL leaves out the prose. Writes needn't be SELECTs, so aren't
+    // checked, and neither are questions asked with `--clarify`.
+    let has_no_query = |text: &String| {
+        let text = sql::extract_sql(text);
+        let is_question = args.clarify && text.trim().starts_with('?');
+        !is_question && !sql::has_select(&text)
+    };
+    let retry = chat_completion_request.stop.is_some() && !args.insecure_allow_writes;
+    if retry && responses.iter().any(has_no_query) {
+        if args.verbose {
+            eprintln!(
+                "A response had no query, perhaps cut off by --stop; asking again without it"
+            );
+        }
+        chat_completion_request.stop = None;
+        let response = create_completion(args, oai_client, chat_completion_request)
+            .await
+            .wrap_err("Failed to open result stream from OpenAI")
+            .kind(ErrorKind::Api)?;
+        responses = response
+            .choices
+            .into_iter()
+            .filter_map(|choice| choice.message.content)
+            .collect();
+    }
+
+    Ok((prompt, responses))
+}
+
+/// Build the messages asking the model to answer `question`, fitting them in the context window.
+async fn assemble_prompt(
+    args: &Args,
+    conn: &mut SqliteConnection,
+    question: &str,
+) -> Result<Vec<(Role, String)>> {
+    // Show the model some real rows so it gets value formats right.
+    let sample_rows = if args.sample_rows > 0 {
+        let sample = db::sample_sections(conn, args.sample_rows)
+            .await
+            .kind(ErrorKind::Db)?;
+        let csv_options = RenderOptions {
+            format: Format::Csv,
+            ..Default::default()
+        };
+        Some(render::render(&sample, "", "", &csv_options)?)
+    } else {
+        None
+    };
+
+    let prompt_options = prompt::PromptOptions {
+        include_online: args.include_online,
+        sample_rows,
+        attached: db::attached(conn).await.kind(ErrorKind::Db)?,
+        json_response: args.json_response,
+        clarify: args.clarify,
+        allow_writes: args.insecure_allow_writes,
+        system_prompt: match &args.system_prompt_file {
+            Some(path) => Some(prompt::load_system_prompt(path).kind(ErrorKind::Usage)?),
+            None => None,
+        },
+        append_system: args.append_system.clone(),
+        attribute_names: args.attribute_names(),
+    };
+    let mut schema = match &args.schema_override {
+        Some(path) => prompt::load_schema_override(path).kind(ErrorKind::Usage)?,
+        None => prompt::DB_INFO_PROMPT.to_string(),
+    };
+    let mut prompt = prompt::build_prompt(&schema, question, &prompt_options);
+
+    // Make sure the prompt will fit in the model's context window.
+    let context_window = args.context_window();
+    let bpe = prompt::tokenizer(&args.model);
+    let tokens = prompt::count_tokens(&prompt, &bpe);
+    if args.verbose {
+        eprintln!("Prompt is {tokens} tokens, of a {context_window}-token context window");
+    }
+    if tokens > context_window {
+        let overflow = tokens - context_window;
+        eprintln!(
+            "warning: prompt is {tokens} tokens, which exceeds the {context_window}-token context window"
+        );
+        if !args.truncate_schema {
+            return Err(eyre::eyre!(
+                "Prompt too large for the context window; pass --truncate-schema to shorten the schema prompt"
+            ))
+            .kind(ErrorKind::Usage);
+        }
+
+        schema = prompt::truncate_schema(&schema, overflow);
+        prompt = prompt::build_prompt(&schema, question, &prompt_options);
+        let tokens = prompt::count_tokens(&prompt, &bpe);
+        eprintln!("warning: truncated schema prompt, leaving a {tokens}-token prompt");
+
+        // Truncation only estimates tokens from characters, and the rest of the prompt (like a
+        // long question or system prompt) may not fit on its own.
+        if tokens > context_window {
+            return Err(eyre::eyre!(
+                "Prompt is still {tokens} tokens after truncating the schema, which exceeds the {context_window}-token context window"
+            ))
+            .kind(ErrorKind::Usage);
+        }
+    }
+
+    Ok(prompt)
+}
+
+/// Ask the model to describe what `sql` does in plain English, for `--explain`.
+pub async fn explain(
+    args: &Args,
+    oai_client: &Client<OpenAIConfig>,
+    question: &str,
+    sql: &str,
+) -> Result<String> {
+    let messages = [
+        (
+            Role::System,
+            "You explain SQL queries against a database of Georgia Tech course sections to students who don't know SQL. In one to three short sentences of plain English, say which sections the query finds (its filters and sorting) and what information it shows (its columns). Don't include any SQL.".to_string(),
+        ),
+        (
+            Role::User,
+            format!("Question: {question}\n\nQuery:\n{sql}"),
+        ),
+    ];
+
+    let request = CreateChatCompletionRequest {
+        model: args.model.to_string(),
+        messages: messages
+            .into_iter()
+            .map(
+                |(role, content)| async_openai::types::ChatCompletionRequestMessage {
+                    role,
+                    content: Some(content),
+                    ..Default::default()
+                },
+            )
+            .collect(),
+        ..Default::default()
+    };
+
+    let response = create_completion(args, oai_client, request)
+        .await
+        .wrap_err("Failed to get an explanation from OpenAI")
+        .kind(ErrorKind::Api)?;
+
+    response
+        .choices
+        .into_iter()
+        .find_map(|choice| choice.message.content)
+        .map(|text| text.trim().to_string())
+        .ok_or_else(|| eyre::eyre!("OpenAI returned no explanation"))
+        .kind(ErrorKind::Api)
+}
+
+/// Times to retry a rate-limited request, and the longest to wait between tries.
+const RATE_LIMIT_RETRIES: usize = 5;
+const MAX_RATE_LIMIT_DELAY: Duration = Duration::from_secs(60);
+
+/// Send a chat completion request, backing off exponentially (from 1 second) and retrying when
+/// OpenAI responds with 429 Too Many Requests. Each try gives up after `--api-timeout-secs`.
+async fn create_completion(
+    args: &Args,
+    oai_client: &Client<OpenAIConfig>,
+    request: CreateChatCompletionRequest,
+) -> Result<CreateChatCompletionResponse> {
+    let send = |request| async move {
+        let chat = oai_client.chat();
+        let response = chat.create(request);
+        if args.api_timeout_secs == 0 {
+            return Ok(response.await);
+        }
+        tokio::time::timeout(Duration::from_secs(args.api_timeout_secs), response)
+            .await
+            .map_err(|_| {
+                eyre::eyre!(
+                    "OpenAI didn't respond within {}s. Check your network connection, or skip the \
+                     API with --cache-file or --offline.",
+                    args.api_timeout_secs
+                )
+            })
+    };
+
+    let mut delay = Duration::from_secs(1);
+    for _ in 0..RATE_LIMIT_RETRIES {
+        match send(request.clone()).await? {
+            Err(OpenAIError::ApiError(err)) if is_rate_limit(&err) => {
+                eprintln!("Rate limited, retrying in {}s…", delay.as_secs());
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RATE_LIMIT_DELAY);
+            }
+            result => return Ok(result?),
+        }
+    }
+    Ok(send(request).await??)
+}
+
+/// Whether an API error is a 429 rate limit, which is worth retrying. Running out of quota also
+/// comes back as a 429, but won't fix itself.
+fn is_rate_limit(err: &ApiError) -> bool {
+    err.code.as_ref().and_then(|code| code.as_str()) == Some("rate_limit_exceeded")
+}
+
+/// Wrap `sql` to drop online sections, if it selects a `campus` or `section` to check.
+async fn exclude_online(conn: &mut SqliteConnection, sql: String) -> String {
+    // If the query doesn't even compile, leave it alone so the error is about the model's SQL.
+    let Ok(columns) = db::column_names(conn, &sql).await else {
+        return sql;
+    };
+    let has_column = |name: &str| columns.iter().any(|column| column == name);
+    sql::exclude_online(&sql, has_column("campus"), has_column("section"))
+}
+
+/// Wrap `sql` to add the instructor's name, if it selects the `crn` and `term` to join on and
+/// doesn't already have an `instructor` column.
+async fn add_instructor(conn: &mut SqliteConnection, sql: String) -> String {
+    // If the query doesn't even compile, leave it alone so the error is about the model's SQL.
+    let Ok(describe) = conn.describe(sql.as_str()).await else {
+        return sql;
+    };
+    let has_column = |name: &str| {
+        describe
+            .columns()
+            .iter()
+            .any(|column| column.name().eq_ignore_ascii_case(name))
+    };
+
+    if !has_column("crn") || !has_column("term") {
+        eprintln!(
+            "warning: the query has no `crn` and `term` columns, so --with-instructor can't add instructors"
+        );
+        return sql;
+    }
+    if has_column("instructor") {
+        return sql;
+    }
+    sql::with_instructor(&sql)
+}
+
+/// With `--with-instructors`, add each row's instructors to `results`, or warn that there's no key
+/// to find them by.
+async fn add_instructors(
+    args: &Args,
+    conn: &mut SqliteConnection,
+    results: &mut ResultSet,
+) -> Result<()> {
+    if !args.with_instructors {
+        return Ok(());
+    }
+    let added = db::add_instructors(conn, results)
+        .await
+        .kind(ErrorKind::Db)?;
+    if !added {
+        eprintln!(
+            "warning: the results have no `id` column, or `crn` and `term` columns, so --with-instructors can't add instructors"
+        );
+    }
+    Ok(())
+}
+
+/// Pick the candidate that returned the most rows, logging the exchange if requested. If every
+/// candidate failed, return the first failure.
+pub async fn pick_best(
+    args: &Args,
+    conn: &mut SqliteConnection,
+    candidates: Candidates,
+) -> Result<Answer> {
+    if let Some(clarification) = &candidates.clarification {
+        eyre::bail!("The model asked for clarification: {clarification}");
+    }
+
+    let mut best: Option<(usize, &ResultSet)> = None;
+    let mut first_err = None;
+    for (i, result) in candidates.results.iter().enumerate() {
+        match result {
+            Ok(rows) if best.is_none_or(|(_, best)| rows.rows.len() > best.rows.len()) => {
+                best = Some((i, rows))
+            }
+            Ok(_) => {}
+            Err(_) => {
+                first_err.get_or_insert(i);
+            }
+        }
+    }
+
+    let (i, rows) = match (best, first_err) {
+        (Some(best), _) => best,
+        (None, Some(i)) => {
+            if let Some(log_path) = &args.log_json {
+                log::append(
+                    log_path,
+                    &candidates.log_record(&args.model, Some((i, None))),
+                )?;
+            }
+            let Err(err) = candidates
+                .results
+                .into_iter()
+                .nth(i)
+                .expect("candidate exists")
+            else {
+                unreachable!("candidate {i} failed");
+            };
+            return Err(err);
+        }
+        (None, None) => unreachable!("at least one candidate was run"),
+    };
+
+    if let Some(log_path) = &args.log_json {
+        let record = candidates.log_record(&args.model, Some((i, Some(rows.rows.len()))));
+        log::append(log_path, &record)?;
+    }
+
+    let sql = candidates.sql[i].clone();
+    let model_sql = candidates.model_sql[i].clone();
+    let Ok(rows) = candidates
+        .results
+        .into_iter()
+        .nth(i)
+        .expect("candidate exists")
+    else {
+        unreachable!("candidate {i} succeeded");
+    };
+    let results = prepare_results(args, conn, rows).await?;
+
+    Ok(Answer {
+        sql,
+        model_sql,
+        results,
+        interrupted: candidates.interrupted,
+    })
+}
+
+/// Decode query results and apply the filtering and column selection requested on the command
+/// line.
+pub async fn prepare_results(
+    args: &Args,
+    conn: &mut SqliteConnection,
+    mut results: ResultSet,
+) -> Result<ResultSet> {
+    if !args.include_online {
+        let excluded = results.exclude_online();
+        if args.verbose && excluded > 0 {
+            eprintln!("Excluded {excluded} online sections (pass --include-online to keep them)");
+        }
+    }
+
+    if args.dedup {
+        match results.dedup_crns() {
+            Some(removed) if args.verbose && removed > 0 => {
+                eprintln!("Merged {removed} rows with duplicate CRNs");
+            }
+            Some(_) => {}
+            None => {
+                eprintln!("warning: the results have no `crn` column, so --dedup has no effect")
+            }
+        }
+    }
+
+    // Before columns are hidden or selected, which can drop the keys instructors are found by.
+    add_instructors(args, conn, &mut results).await?;
+
+    // Sorting and grouping happen before columns are hidden or selected, which can drop their
+    // keys. Grouping sorts stably, so `--sort` orders the rows within each group.
+    if !args.sort.is_empty() {
+        let missing = results.sort_by_columns(&args.sort);
+        if !missing.is_empty() {
+            return Err(eyre::eyre!(
+                "Can't sort by {}: not in the results. Available columns: {}",
+                missing.join(", "),
+                results.columns.join(", ")
+            ))
+            .kind(ErrorKind::Usage);
+        }
+    }
+
+    // After sorting, so the sort decides which row is kept for each value.
+    if let Some(column) = &args.distinct_column {
+        match results.distinct_by(column) {
+            Some(removed) if args.verbose && removed > 0 => {
+                eprintln!("Removed {removed} rows with a repeated `{column}`");
+            }
+            Some(_) => {}
+            None => {
+                let suggestion = render::closest_name(column, &results.columns)
+                    .map(|name| format!(" Did you mean `{name}`?"))
+                    .unwrap_or_default();
+                return Err(eyre::eyre!(
+                    "Can't make `{column}` distinct: not in the results.{suggestion} Available columns: {}",
+                    results.columns.join(", ")
+                ))
+                .kind(ErrorKind::Usage);
+            }
+        }
+    }
+
+    if !args.group_by.is_empty() {
+        let missing = results.sort_by_columns(&args.group_by);
+        if !missing.is_empty() {
+            return Err(eyre::eyre!(
+                "Can't group by {}: not in the results. Available columns: {}",
+                missing.join(", "),
+                results.columns.join(", ")
+            ))
+            .kind(ErrorKind::Usage);
+        }
+    }
+
+    // Hiding a column that isn't there is fine (`raw` usually isn't), unless it looks like a typo.
+    let columns = results.columns.clone();
+    let hide_columns: Vec<String> = args
+        .hide_columns
+        .iter()
+        .filter(|column| !(args.show_raw && column.eq_ignore_ascii_case("raw")))
+        .cloned()
+        .collect();
+    let missing = results.hide_columns(&hide_columns);
+    if hide_columns == ["raw"] && !missing.contains(&hide_columns[0]) && !args.quiet {
+        // Once, since this runs for every candidate.
+        static NOTED: std::sync::Once = std::sync::Once::new();
+        NOTED.call_once(|| eprintln!("note: hid the `raw` column; pass --show-raw to see it"));
+    }
+    for missing in missing {
+        if let Some(suggestion) = render::closest_name(&missing, &columns) {
+            eprintln!(
+                "warning: column `{missing}` isn't in the results; did you mean `{suggestion}`?"
+            );
+        }
+    }
+
+    if !args.show_columns.is_empty() {
+        let columns = results.columns.clone();
+        for missing in results.select_columns(&args.show_columns) {
+            match render::closest_name(&missing, &columns) {
+                Some(suggestion) => eprintln!(
+                    "warning: column `{missing}` isn't in the results; did you mean `{suggestion}`?"
+                ),
+                None => eprintln!("warning: column `{missing}` isn't in the results"),
+            }
+        }
+    }
+
+    // After hiding columns, since every other column is part of a pivoted row's key.
+    if let Some(column) = &args.pivot {
+        let columns = results.columns.clone();
+        let missing = results.pivot(column, args.pivot_value.as_deref());
+        if let Some(missing) = missing.first() {
+            let suggestion = render::closest_name(missing, &columns)
+                .map(|name| format!(" Did you mean `{name}`?"))
+                .unwrap_or_default();
+            return Err(eyre::eyre!(
+                "Can't pivot on `{missing}`: not in the results.{suggestion} Available columns: {}",
+                columns.join(", ")
+            ))
+            .kind(ErrorKind::Usage);
+        }
+    }
+
+    if let Some(n) = args.last {
+        let skipped = results.rows.len().saturating_sub(n);
+        results.rows.drain(..skipped);
+    }
+
+    if args.row_numbers {
+        results.number_rows();
+    }
+
+    Ok(results)
+}
@@ -0,0 +1,110 @@
+use async_openai::config::OpenAIConfig;
+use async_openai::Client;
+use eyre::{Context, Result};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use sqlx::sqlite::SqliteConnection;
+use std::path::Path;
+
+use crate::error::{ErrorKind, WithKind};
+use crate::render::{self, Format, RenderOptions};
+use crate::{agent, cache, Args};
+
+#[derive(serde::Deserialize)]
+struct Question {
+    question: String,
+}
+
+/// Answer every question in the CSV file at `csv_input`, writing each one's results to a
+/// numbered file in `--output-dir`.
+pub async fn run(
+    args: &Args,
+    conn: &mut SqliteConnection,
+    oai_client: &Client<OpenAIConfig>,
+    csv_input: &Path,
+) -> Result<()> {
+    let output_dir = args
+        .output_dir
+        .as_deref()
+        .expect("clap requires --output-dir");
+
+    // Results go to files for other programs to read, so they're CSV unless asked otherwise, and
+    // never terminal tables.
+    let format = args.format.unwrap_or(Format::Csv);
+    if format == Format::Table {
+        return Err(eyre::eyre!(
+            "--csv-input writes results to files, so it can't use --format table; use csv, tsv, json, markdown, or html"
+        ))
+        .kind(ErrorKind::Usage);
+    }
+
+    let questions: Vec<Question> = csv::Reader::from_path(csv_input)
+        .and_then(|mut reader| reader.deserialize().collect())
+        .wrap_err_with(|| format!("Failed to read questions from {}", csv_input.display()))
+        .kind(ErrorKind::Usage)?;
+
+    std::fs::create_dir_all(output_dir)
+        .wrap_err_with(|| format!("Failed to create {}", output_dir.display()))?;
+
+    // There's no terminal to color or link for.
+    let render_options = RenderOptions {
+        format,
+        color: None,
+        highlight_terms: vec![],
+        hyperlink_url: None,
+        ..args.render_options()
+    };
+    let extension = match format {
+        Format::Table => unreachable!("rejected above"),
+        Format::Json | Format::JsonPretty => "json",
+        Format::Csv => "csv",
+        Format::Tsv => "tsv",
+        Format::Markdown => "md",
+        Format::Html => "html",
+    };
+
+    let progress = ProgressBar::new(questions.len() as u64).with_style(
+        ProgressStyle::with_template("{bar:40} {pos}/{len} {wide_msg}")
+            .expect("progress template is valid"),
+    );
+    if args.quiet {
+        progress.set_draw_target(ProgressDrawTarget::hidden());
+    }
+
+    let mut failures = 0;
+    for (i, Question { question }) in questions.iter().enumerate() {
+        progress.set_message(question.clone());
+
+        let answer = match agent::run_candidates(args, conn, oai_client, question).await {
+            Ok(candidates) => agent::pick_best(args, conn, candidates).await,
+            Err(err) => Err(err),
+        };
+
+        match answer {
+            Ok(answer) => {
+                if let (Some(path), None) = (&args.cache_file, &args.sql) {
+                    cache::store(path, question, &answer.model_sql)?;
+                }
+
+                let path = output_dir.join(format!("{:03}.{extension}", i + 1));
+                let rendered =
+                    render::render(&answer.results, question, &answer.sql, &render_options)?;
+                std::fs::write(&path, rendered)
+                    .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+            }
+            Err(err) => {
+                failures += 1;
+                progress.println(format!("Question {} ({question}) failed: {err:#}", i + 1));
+            }
+        }
+
+        progress.inc(1);
+    }
+
+    progress.finish_and_clear();
+
+    if failures > 0 {
+        eyre::bail!("{failures} of {} questions failed", questions.len());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use eyre::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// SQL for previously answered questions, stored as a JSON object keyed by question.
+type Cache = BTreeMap<String, String>;
+
+fn load(path: &Path) -> Result<Cache> {
+    if !path.exists() {
+        return Ok(Cache::new());
+    }
+
+    let text = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read cache file {}", path.display()))?;
+    serde_json::from_str(&text).wrap_err_with(|| {
+        format!(
+            "Cache file {} is not a JSON object of strings",
+            path.display()
+        )
+    })
+}
+
+/// The cached SQL for `question`, if any.
+pub fn lookup(path: &Path, question: &str) -> Result<Option<String>> {
+    Ok(load(path)?.remove(question.trim()))
+}
+
+/// Remember the SQL that answered `question`.
+pub fn store(path: &Path, question: &str, sql: &str) -> Result<()> {
+    let mut cache = load(path)?;
+    cache.insert(question.trim().to_string(), sql.to_string());
+
+    let text = serde_json::to_string_pretty(&cache)?;
+    std::fs::write(path, text + "\n")
+        .wrap_err_with(|| format!("Failed to write cache file {}", path.display()))
+}
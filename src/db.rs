@@ -0,0 +1,523 @@
+use eyre::{Context, Result};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use sqlx::{Column, ConnectOptions, Executor, Statement};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::prompt::DB_INFO_PROMPT;
+use crate::render::{self, ResultSet, Value};
+
+/// Options for connecting to `db`: a path, a `sqlite:` URL, or a `file:` URI, which can set
+/// options like `?mode=ro&cache=shared`.
+fn connect_options(db: &str) -> Result<SqliteConnectOptions> {
+    let url = match db.strip_prefix("file:") {
+        Some(uri) => format!("sqlite:{uri}"),
+        None => db.to_string(),
+    };
+    SqliteConnectOptions::from_str(&url).wrap_err("Invalid db connection string")
+}
+
+/// Open the course database, checking that it exists and looks like a course database. It's
+/// read-only if `read_only` is set or the URL says so. Statements wait up to `busy_timeout` for
+/// another process's lock on the database.
+pub async fn open(db: &str, read_only: bool, busy_timeout: Duration) -> Result<SqliteConnection> {
+    // Only check plain paths; URLs are passed through as-is.
+    let is_url = db.starts_with("sqlite:") || db.starts_with("file:");
+    if !is_url && !Path::new(db).exists() {
+        let abs_path = std::path::absolute(db).unwrap_or_else(|_| PathBuf::from(db));
+        eyre::bail!(
+            "Database file not found: {}\n\
+             Pass the path to your course database with `--db <path>` (default: `courses.sqlite3`).",
+            abs_path.display()
+        );
+    }
+
+    let mut options = connect_options(db)?.busy_timeout(busy_timeout);
+    // Don't undo a URL's `mode=ro`.
+    if read_only {
+        options = options.read_only(true);
+    }
+    let mut conn = options
+        .connect()
+        .await
+        .wrap_err_with(|| format!("Failed to open database `{db}`"))?;
+
+    // Make sure this is a sqlite database, and that it has the table we prompt the model with.
+    let has_sections: Option<(String,)> = sqlx::query_as(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'sections'",
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .map_err(|err| {
+        // SQLITE_BUSY, once the busy timeout is up.
+        let locked = err
+            .as_database_error()
+            .and_then(|err| err.code())
+            .is_some_and(|code| code == "5");
+        let message = if locked {
+            format!("`{db}` is locked by another process; pass --busy-timeout-secs to wait longer")
+        } else {
+            format!("`{db}` is not a SQLite database")
+        };
+        eyre::Report::new(err).wrap_err(message)
+    })?;
+
+    if has_sections.is_none() {
+        eyre::bail!("`{db}` is a SQLite database, but it has no `sections` table");
+    }
+
+    Ok(conn)
+}
+
+/// Attach more course databases to `conn`, under names derived from their filenames. They're
+/// read-only, like the main database.
+pub async fn attach(conn: &mut SqliteConnection, dbs: &[String]) -> Result<()> {
+    let mut names: Vec<String> = vec![];
+    for db in dbs {
+        if !Path::new(db).exists() {
+            let abs_path = std::path::absolute(db).unwrap_or_else(|_| PathBuf::from(db));
+            eyre::bail!("Database file not found: {}", abs_path.display());
+        }
+
+        let name = unique_name(&schema_name(db), &names);
+        sqlx::query(&format!("ATTACH DATABASE ? AS {name}"))
+            .bind(db)
+            .execute(&mut *conn)
+            .await
+            .wrap_err_with(|| format!("Failed to attach `{db}`"))?;
+
+        let has_sections: Option<(String,)> = sqlx::query_as(&format!(
+            "SELECT name FROM {name}.sqlite_master WHERE type = 'table' AND name = 'sections'"
+        ))
+        .fetch_optional(&mut *conn)
+        .await
+        .wrap_err_with(|| format!("`{db}` is not a SQLite database"))?;
+        if has_sections.is_none() {
+            eyre::bail!("`{db}` is a SQLite database, but it has no `sections` table");
+        }
+
+        names.push(name);
+    }
+
+    Ok(())
+}
+
+/// The attached databases, as (schema name, file) pairs.
+pub async fn attached(conn: &mut SqliteConnection) -> Result<Vec<(String, String)>> {
+    sqlx::query_as(
+        "SELECT name, file FROM pragma_database_list WHERE name NOT IN ('main', 'temp') ORDER BY seq",
+    )
+    .fetch_all(conn)
+    .await
+    .wrap_err("Failed to list attached databases")
+}
+
+/// Turn a database path into a schema name that's a plain SQL identifier, like
+/// `fall_2023` for `data/Fall 2023.sqlite3`.
+fn schema_name(db: &str) -> String {
+    let stem = Path::new(db)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let name: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        _ => format!("db_{name}"),
+    }
+}
+
+/// Add a numeric suffix to `name` if it's reserved or already taken.
+fn unique_name(name: &str, taken: &[String]) -> String {
+    let is_free = |candidate: &str| {
+        !matches!(candidate, "main" | "temp") && !taken.iter().any(|t| t == candidate)
+    };
+    if is_free(name) {
+        return name.to_string();
+    }
+    (2..)
+        .map(|i| format!("{name}_{i}"))
+        .find(|candidate| is_free(candidate))
+        .expect("some suffix is free")
+}
+
+/// Full-text index over course titles, used by `search`. Kept in sync with `sections` by triggers.
+/// Requires SQLite built with FTS5, which the bundled SQLite is.
+const FTS_SCHEMA: &str = r#"
+CREATE VIRTUAL TABLE sections_fts USING fts5(
+    course_title, subject, number, crn,
+    content = 'sections', content_rowid = 'rowid'
+);
+
+CREATE TRIGGER sections_fts_insert AFTER INSERT ON sections BEGIN
+    INSERT INTO sections_fts (rowid, course_title, subject, number, crn)
+    VALUES (new.rowid, new.course_title, new.subject, new.number, new.crn);
+END;
+
+CREATE TRIGGER sections_fts_delete AFTER DELETE ON sections BEGIN
+    INSERT INTO sections_fts (sections_fts, rowid, course_title, subject, number, crn)
+    VALUES ('delete', old.rowid, old.course_title, old.subject, old.number, old.crn);
+END;
+
+CREATE TRIGGER sections_fts_update AFTER UPDATE ON sections BEGIN
+    INSERT INTO sections_fts (sections_fts, rowid, course_title, subject, number, crn)
+    VALUES ('delete', old.rowid, old.course_title, old.subject, old.number, old.crn);
+    INSERT INTO sections_fts (rowid, course_title, subject, number, crn)
+    VALUES (new.rowid, new.course_title, new.subject, new.number, new.crn);
+END;
+
+-- Index any rows that are already there.
+INSERT INTO sections_fts (sections_fts) VALUES ('rebuild');
+"#;
+
+/// Whether the database has the `sections_fts` full-text index.
+pub async fn has_fts(conn: &mut SqliteConnection) -> Result<bool> {
+    let table: Option<(String,)> = sqlx::query_as(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'sections_fts'",
+    )
+    .fetch_optional(conn)
+    .await
+    .wrap_err("Failed to check for the full-text index")?;
+
+    Ok(table.is_some())
+}
+
+/// Check that `sql` only names tables and columns that exist, without running it, so a made-up
+/// name gets a clear error with a suggestion. Other problems are left for running it to report.
+pub async fn check_identifiers(conn: &mut SqliteConnection, sql: &str) -> Result<()> {
+    // Preparing the statement resolves every name in it against the schema, including attached
+    // databases, CTEs, and aliases, which a SQL parser would have to resolve itself.
+    let message = match (&mut *conn).prepare(sql).await {
+        Err(sqlx::Error::Database(err)) => err.message().to_string(),
+        _ => return Ok(()),
+    };
+
+    let tables = table_columns(conn).await?;
+    // Tables the query mentions, by a rough word match; aliases don't matter here. Words keep
+    // their dots, so `fall_2023.sections` doesn't also mention the main `sections`.
+    let words: Vec<String> = sql
+        .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+        .map(str::to_lowercase)
+        .collect();
+    let mentioned: Vec<&(String, Vec<String>)> = tables
+        .iter()
+        .filter(|(table, _)| {
+            let table = table.to_lowercase();
+            words.contains(&table) || words.contains(&format!("main.{table}"))
+        })
+        .collect();
+
+    if let Some(name) = message.strip_prefix("no such column: ") {
+        // Qualified names like `s.days` are matched by their column.
+        let column = name.rsplit('.').next().unwrap_or(name);
+        let candidates: Vec<String> = if mentioned.is_empty() {
+            tables
+                .iter()
+                .flat_map(|(_, columns)| columns.clone())
+                .collect()
+        } else {
+            mentioned
+                .iter()
+                .flat_map(|(_, columns)| columns.clone())
+                .collect()
+        };
+        let mut error = match mentioned.as_slice() {
+            [(table, _)] => format!("unknown column `{name}` on table `{table}`"),
+            _ => format!("unknown column `{name}`"),
+        };
+        if let Some(suggestion) = render::closest_name(column, &candidates) {
+            error += &format!("; did you mean `{suggestion}`?");
+        }
+        eyre::bail!(error);
+    }
+
+    if let Some(table) = message.strip_prefix("no such table: ") {
+        let names: Vec<String> = tables.into_iter().map(|(table, _)| table).collect();
+        match render::closest_name(table, &names) {
+            Some(suggestion) => {
+                eyre::bail!("unknown table `{table}`; did you mean `{suggestion}`?")
+            }
+            None => eyre::bail!("unknown table `{table}`. Tables: {}", names.join(", ")),
+        }
+    }
+
+    Ok(())
+}
+
+/// Every table and view in the main database and any attached ones, with its columns. Tables in
+/// attached databases are named with their schema, like `fall_2023.sections`.
+async fn table_columns(conn: &mut SqliteConnection) -> Result<Vec<(String, Vec<String>)>> {
+    let schemas: Vec<(String,)> =
+        sqlx::query_as("SELECT name FROM pragma_database_list WHERE name != 'temp' ORDER BY seq")
+            .fetch_all(&mut *conn)
+            .await
+            .wrap_err("Failed to list databases")?;
+
+    let mut table_columns = vec![];
+    for (schema,) in schemas {
+        let tables: Vec<(String,)> = sqlx::query_as(&format!(
+            "SELECT name FROM \"{schema}\".sqlite_master WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%' ORDER BY name"
+        ))
+        .fetch_all(&mut *conn)
+        .await
+        .wrap_err("Failed to list tables")?;
+
+        for (table,) in tables {
+            let columns: Vec<(String,)> =
+                sqlx::query_as("SELECT name FROM pragma_table_info(?, ?)")
+                    .bind(&table)
+                    .bind(&schema)
+                    .fetch_all(&mut *conn)
+                    .await
+                    .wrap_err_with(|| format!("Failed to list the columns of `{table}`"))?;
+            let name = match schema.as_str() {
+                "main" => table,
+                _ => format!("{schema}.{table}"),
+            };
+            table_columns.push((name, columns.into_iter().map(|(name,)| name).collect()));
+        }
+    }
+    Ok(table_columns)
+}
+
+/// The names of the columns `sql` returns, from the prepared statement.
+pub async fn column_names(conn: &mut SqliteConnection, sql: &str) -> sqlx::Result<Vec<String>> {
+    let statement = (&mut *conn).prepare(sql).await?;
+    Ok(statement
+        .columns()
+        .iter()
+        .map(|column| column.name().to_string())
+        .collect())
+}
+
+/// Run `sql` and decode all of its rows.
+pub async fn fetch_results(conn: &mut SqliteConnection, sql: &str) -> Result<ResultSet> {
+    let columns = column_names(conn, sql).await?;
+    let rows = conn.fetch_all(sql).await?;
+    ResultSet::from_rows(columns, &rows)
+}
+
+/// Fetch `n` random rows from `sections`.
+pub async fn sample_sections(conn: &mut SqliteConnection, n: u32) -> Result<ResultSet> {
+    const SAMPLE: &str = "SELECT * FROM sections ORDER BY RANDOM() LIMIT ?";
+    let columns = column_names(conn, SAMPLE).await?;
+    let rows = sqlx::query(SAMPLE)
+        .bind(n)
+        .fetch_all(conn)
+        .await
+        .wrap_err("Failed to sample rows from `sections`")?;
+
+    // The scraped source data would swamp the prompt.
+    let mut sample = ResultSet::from_rows(columns, &rows)?;
+    sample.hide_columns(&["raw".to_string()]);
+    Ok(sample)
+}
+
+/// Instructors listed by name in each row's `instructors` cell, before the rest are counted.
+const MAX_INSTRUCTORS: usize = 3;
+
+/// Add an `instructors` column to `results` with the names of each row's instructors, found by
+/// the section `id`, or else by `crn` and `term`. Returns false, leaving `results` alone, if it
+/// has neither.
+pub async fn add_instructors(conn: &mut SqliteConnection, results: &mut ResultSet) -> Result<bool> {
+    let position = |name: &str| {
+        results
+            .columns
+            .iter()
+            .position(|column| column.eq_ignore_ascii_case(name))
+    };
+    // Each row's key, and the column that's looked up (with the key of each instructor found).
+    let (keys, lookup, query): (Vec<String>, usize, &str) =
+        match (position("id"), position("crn"), position("term")) {
+            (Some(id), _, _) => (
+                results.rows.iter().map(|row| row[id].to_string()).collect(),
+                id,
+                "SELECT cf.course_id, f.name
+                FROM course_faculty cf
+                JOIN faculty f ON f.id = cf.faculty_id
+                WHERE cf.course_id IN",
+            ),
+            (None, Some(crn), Some(term)) => (
+                results
+                    .rows
+                    .iter()
+                    .map(|row| format!("{}/{}", row[crn], row[term]))
+                    .collect(),
+                crn,
+                "SELECT s.crn || '/' || s.term, f.name
+                FROM sections s
+                JOIN course_faculty cf ON cf.course_id = s.id
+                JOIN faculty f ON f.id = cf.faculty_id
+                WHERE s.crn IN",
+            ),
+            _ => return Ok(false),
+        };
+
+    let mut values: Vec<String> = results
+        .rows
+        .iter()
+        .map(|row| row[lookup].to_string())
+        .collect();
+    values.sort();
+    values.dedup();
+
+    // A chunk at a time, to stay under SQLite's limit on parameters.
+    let mut names: HashMap<String, Vec<String>> = HashMap::new();
+    for chunk in values.chunks(500) {
+        let placeholders = vec!["?"; chunk.len()].join(", ");
+        let sql = format!("{query} ({placeholders}) ORDER BY cf.rowid");
+        let mut instructors = sqlx::query_as::<_, (String, String)>(&sql);
+        for value in chunk {
+            instructors = instructors.bind(value);
+        }
+        for (key, name) in instructors
+            .fetch_all(&mut *conn)
+            .await
+            .wrap_err("Failed to look up instructors")?
+        {
+            names.entry(key).or_default().push(name);
+        }
+    }
+
+    results.columns.push("instructors".to_string());
+    for (row, key) in results.rows.iter_mut().zip(keys) {
+        let cell = match names.get(&key).map(Vec::as_slice) {
+            None | Some([]) => Value::Null,
+            Some(names) if names.len() <= MAX_INSTRUCTORS => Value::Text(names.join(", ")),
+            Some(names) => Value::Text(format!(
+                "{} +{} more",
+                names[..MAX_INSTRUCTORS].join(", "),
+                names.len() - MAX_INSTRUCTORS
+            )),
+        };
+        row.push(cell);
+    }
+    Ok(true)
+}
+
+/// Create a new, empty course database at `db` using the schema from the prompt.
+pub async fn init(db: &str, overwrite: bool) -> Result<()> {
+    let path = Path::new(db);
+    if path.exists() {
+        if !overwrite {
+            eyre::bail!("`{db}` already exists; pass --overwrite to replace it");
+        }
+        std::fs::remove_file(path).wrap_err_with(|| format!("Failed to remove `{db}`"))?;
+    }
+
+    let mut conn = connect_options(db)?
+        .create_if_missing(true)
+        .connect()
+        .await
+        .wrap_err_with(|| format!("Failed to create database `{db}`"))?;
+
+    // The schema in the prompt is plain SQLite DDL, so it can be run as-is.
+    conn.execute(DB_INFO_PROMPT)
+        .await
+        .wrap_err("Failed to create schema")?;
+
+    conn.execute(FTS_SCHEMA)
+        .await
+        .wrap_err("Failed to create full-text index (SQLite must be built with FTS5)")?;
+
+    eprintln!("Created {db}");
+
+    Ok(())
+}
+
+/// Add the full-text index to an existing course database.
+pub async fn add_fts(db: &str, busy_timeout: Duration) -> Result<()> {
+    // Check that it's a course database first.
+    let mut conn = open(db, true, busy_timeout).await?;
+    if has_fts(&mut conn).await? {
+        eyre::bail!("`{db}` already has a full-text index");
+    }
+
+    let mut conn = connect_options(db)?
+        .busy_timeout(busy_timeout)
+        .connect()
+        .await
+        .wrap_err_with(|| format!("Failed to open database `{db}` for writing"))?;
+
+    conn.execute(FTS_SCHEMA)
+        .await
+        .wrap_err("Failed to create full-text index (SQLite must be built with FTS5)")?;
+
+    eprintln!("Added full-text index to {db}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An in-memory course database, with another attached as `fall_2023`.
+    async fn course_db() -> SqliteConnection {
+        crate::error::install();
+        let mut conn = SqliteConnectOptions::from_str("sqlite::memory:")
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        conn.execute(DB_INFO_PROMPT).await.unwrap();
+        conn.execute(
+            "ATTACH DATABASE ':memory:' AS fall_2023; CREATE TABLE fall_2023.sections (crn text)",
+        )
+        .await
+        .unwrap();
+        conn
+    }
+
+    async fn check(sql: &str) -> Result<()> {
+        check_identifiers(&mut course_db().await, sql).await
+    }
+
+    #[tokio::test]
+    async fn known_names_pass() {
+        assert!(check("SELECT crn, term FROM sections").await.is_ok());
+        assert!(check("SELECT crn FROM fall_2023.sections").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn unknown_tables_get_a_suggestion() {
+        let err = check("SELECT crn FROM sectons").await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown table `sectons`; did you mean `sections`?"
+        );
+
+        let err = check("SELECT crn FROM fall_2023.sectons")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown table `fall_2023.sectons`; did you mean `fall_2023.sections`?"
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_columns_get_a_suggestion() {
+        let err = check("SELECT crns FROM sections").await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown column `crns` on table `sections`; did you mean `crn`?"
+        );
+
+        let err = check("SELECT s.terms FROM sections s").await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown column `s.terms` on table `sections`; did you mean `term`?"
+        );
+    }
+
+    #[tokio::test]
+    async fn other_errors_are_left_for_running() {
+        assert!(check("SELEC nope").await.is_ok());
+    }
+}
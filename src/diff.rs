@@ -0,0 +1,79 @@
+use eyre::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::render::{ResultSet, Value};
+
+/// Results as text, which is how `ResultSet::diff` compares them.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StoredResults {
+    columns: Vec<String>,
+    rows: Vec<Vec<Option<String>>>,
+}
+
+/// The last results for each question, stored as a JSON object keyed by question.
+type History = BTreeMap<String, StoredResults>;
+
+/// `$XDG_STATE_HOME/reg-agent/previous-results.json`, or under `~/.local/state` by default.
+fn path() -> Result<PathBuf> {
+    let state_dir = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .ok_or_else(|| eyre::eyre!("Can't find a state directory; set HOME or XDG_STATE_HOME"))?;
+    Ok(state_dir.join("reg-agent").join("previous-results.json"))
+}
+
+fn load() -> Result<History> {
+    let path = path()?;
+    if !path.exists() {
+        return Ok(History::new());
+    }
+
+    let text = std::fs::read_to_string(&path)
+        .wrap_err_with(|| format!("Failed to read previous results from {}", path.display()))?;
+    serde_json::from_str(&text)
+        .wrap_err_with(|| format!("Previous results file {} is invalid", path.display()))
+}
+
+/// Remember `results` as the answer to `question`, returning the results it last had, if any.
+pub fn swap(question: &str, results: &ResultSet) -> Result<Option<ResultSet>> {
+    let mut history = load()?;
+    let stored = StoredResults {
+        columns: results.columns.clone(),
+        rows: results
+            .rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|value| match value {
+                        Value::Null => None,
+                        value => Some(value.to_string()),
+                    })
+                    .collect()
+            })
+            .collect(),
+    };
+    let previous = history.insert(question.trim().to_string(), stored);
+
+    let path = path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .wrap_err_with(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let text = serde_json::to_string_pretty(&history)?;
+    std::fs::write(&path, text + "\n")
+        .wrap_err_with(|| format!("Failed to write previous results to {}", path.display()))?;
+
+    Ok(previous.map(|previous| ResultSet {
+        columns: previous.columns,
+        rows: previous
+            .rows
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|value| value.map_or(Value::Null, Value::Text))
+                    .collect()
+            })
+            .collect(),
+    }))
+}
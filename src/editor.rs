@@ -0,0 +1,59 @@
+use eyre::{Context, Result};
+use std::process::Command;
+
+const INSTRUCTIONS: &str = "
+# Write your question above. Lines starting with '#' are ignored, and an empty
+# question aborts.
+";
+
+/// Have the user write a question in `$VISUAL` or `$EDITOR` (or `vi`), like `git commit` does.
+pub fn question() -> Result<String> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .find_map(|var| {
+            std::env::var(var)
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+        })
+        .unwrap_or_else(|| "vi".to_string());
+
+    // A new file with a random name, so nobody else sharing the temp directory can swap in a
+    // symlink. It's deleted when dropped.
+    let file = tempfile::Builder::new()
+        .prefix("reg-agent-question-")
+        .suffix(".txt")
+        .tempfile()
+        .wrap_err("Failed to create a file for the question")?;
+    let path = file.path();
+    std::fs::write(path, INSTRUCTIONS)
+        .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+
+    // The editor may have arguments, like `code --wait`, so let the shell split it.
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .wrap_err_with(|| format!("Failed to run editor `{editor}`"));
+    let contents = std::fs::read_to_string(path);
+    drop(file);
+
+    if !status?.success() {
+        eyre::bail!("Editor `{editor}` exited with an error");
+    }
+    let contents = contents.wrap_err("Failed to read the question back from the editor")?;
+
+    let question = contents
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string();
+    if question.is_empty() {
+        eyre::bail!("Aborting because the question is empty");
+    }
+
+    Ok(question)
+}
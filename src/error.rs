@@ -0,0 +1,154 @@
+use eyre::{DefaultHandler, EyreHandler};
+use std::error::Error as StdError;
+use std::fmt;
+
+/// Documentation for `--help`. Keep in sync with `ErrorKind::exit_code` and the constants below.
+pub const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0   Success
+  1   Other error, or a failed --assert-nonempty or --assert-max-rows check
+  2   Usage error
+  3   Database error (missing or invalid database)
+  4   LLM/API error
+  5   SQL error (the model produced bad SQL)
+  8   The query ran successfully, but returned no rows
+  9   The model asked a clarifying question instead (--clarify)
+  78  No API key configured
+  130 Interrupted with Ctrl-C while fetching rows (the rows so far are still shown)";
+
+/// Exit code when the query succeeds but returns no rows.
+pub const EXIT_NO_ROWS: i32 = 8;
+
+/// Exit code when the model asks a clarifying question instead of writing SQL.
+pub const EXIT_CLARIFY: i32 = 9;
+
+/// Exit code when Ctrl-C stops fetching rows early (128 + SIGINT, as shells report it).
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// Exit code when no API key is configured (`EX_CONFIG` from sysexits.h).
+pub const EXIT_NO_API_KEY: i32 = 78;
+
+/// The broad class of a failure, which determines the process exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Usage,
+    Db,
+    Api,
+    Sql,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Usage => 2,
+            ErrorKind::Db => 3,
+            ErrorKind::Api => 4,
+            ErrorKind::Sql => 5,
+        }
+    }
+}
+
+/// Report handler that also carries the error's `ErrorKind`, so the kind can decide the exit
+/// code without taking over the message. Reports print as with eyre's default handler.
+struct Handler {
+    kind: Option<ErrorKind>,
+    default: Box<dyn EyreHandler>,
+}
+
+impl EyreHandler for Handler {
+    fn debug(&self, error: &(dyn StdError + 'static), f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.default.debug(error, f)
+    }
+
+    fn display(&self, error: &(dyn StdError + 'static), f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.default.display(error, f)
+    }
+
+    fn track_caller(&mut self, location: &'static std::panic::Location<'static>) {
+        self.default.track_caller(location);
+    }
+}
+
+/// Use the report handler that `WithKind::kind` records kinds in. Must be called before any
+/// error is created, or kinds are dropped and every error exits with 1.
+pub fn install() {
+    // This only fails if a handler is already installed.
+    let _ = eyre::set_hook(Box::new(|error| {
+        Box::new(Handler {
+            kind: None,
+            default: DefaultHandler::default_with(error),
+        })
+    }));
+}
+
+pub trait WithKind<T> {
+    /// Tag an error with the kind of failure it represents, replacing any kind it had.
+    fn kind(self, kind: ErrorKind) -> eyre::Result<T>;
+}
+
+impl<T> WithKind<T> for eyre::Result<T> {
+    fn kind(self, kind: ErrorKind) -> eyre::Result<T> {
+        self.map_err(|mut err| {
+            if let Some(handler) = err.handler_mut().downcast_mut::<Handler>() {
+                handler.kind = Some(kind);
+            }
+            err
+        })
+    }
+}
+
+/// Exit code for an error, using the last `ErrorKind` it was tagged with.
+pub fn exit_code(err: &eyre::Report) -> i32 {
+    err.handler()
+        .downcast_ref::<Handler>()
+        .and_then(|handler| handler.kind)
+        .map_or(1, ErrorKind::exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eyre::WrapErr;
+
+    fn tagged(kind: ErrorKind) -> eyre::Report {
+        install();
+        Err::<(), _>(eyre::eyre!("Database file not found"))
+            .kind(kind)
+            .unwrap_err()
+    }
+
+    #[test]
+    fn each_kind_has_its_exit_code() {
+        for (kind, code) in [
+            (ErrorKind::Usage, 2),
+            (ErrorKind::Db, 3),
+            (ErrorKind::Api, 4),
+            (ErrorKind::Sql, 5),
+        ] {
+            assert_eq!(exit_code(&tagged(kind)), code);
+        }
+    }
+
+    #[test]
+    fn untagged_errors_exit_with_1() {
+        install();
+        assert_eq!(exit_code(&eyre::eyre!("Something else")), 1);
+    }
+
+    #[test]
+    fn the_message_stays_the_headline() {
+        let err = tagged(ErrorKind::Db);
+        assert_eq!(err.to_string(), "Database file not found");
+        assert!(format!("{err:?}").starts_with("Database file not found"));
+    }
+
+    #[test]
+    fn the_last_kind_wins_and_survives_context() {
+        let err = Err::<(), _>(tagged(ErrorKind::Db))
+            .kind(ErrorKind::Usage)
+            .wrap_err("Failed to open the database")
+            .unwrap_err();
+        assert_eq!(exit_code(&err), 2);
+        assert_eq!(err.to_string(), "Failed to open the database");
+    }
+}
@@ -0,0 +1,152 @@
+use eyre::{Context, Result};
+use sqlx::sqlite::SqliteConnection;
+use sqlx::Executor;
+use std::io::Write;
+use tokio_stream::StreamExt;
+
+use crate::db;
+use crate::error::{ErrorKind, WithKind};
+use crate::render::{JsonRow, ResultSet};
+
+/// Formats for `export`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+
+    /// A JSON array of objects, one per row.
+    Json,
+}
+
+/// What to dump with `export`.
+pub struct ExportOptions<'a> {
+    pub table: &'a str,
+    pub format: ExportFormat,
+    pub include_raw: bool,
+}
+
+/// Write every row of a table to `out`, as rows are read, without loading the whole table.
+/// Values are written as stored, so the dump is the same every time.
+pub async fn export(
+    conn: &mut SqliteConnection,
+    options: &ExportOptions<'_>,
+    out: impl Write,
+) -> Result<()> {
+    // The table name can't be a bound parameter, so make sure it's a real one before quoting it.
+    let exists: Option<(String,)> = sqlx::query_as(
+        "SELECT name FROM sqlite_master WHERE type IN ('table', 'view') AND name = ?",
+    )
+    .bind(options.table)
+    .fetch_optional(&mut *conn)
+    .await
+    .wrap_err("Failed to list tables")
+    .kind(ErrorKind::Db)?;
+    if exists.is_none() {
+        let tables: Vec<(String,)> = sqlx::query_as(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )
+        .fetch_all(&mut *conn)
+        .await
+        .wrap_err("Failed to list tables")
+        .kind(ErrorKind::Db)?;
+        let tables: Vec<String> = tables.into_iter().map(|(name,)| name).collect();
+        return Err(eyre::eyre!(
+            "No table named `{}`. Tables: {}",
+            options.table,
+            tables.join(", ")
+        ))
+        .kind(ErrorKind::Usage);
+    }
+
+    let query = format!(
+        "SELECT * FROM \"{}\" ORDER BY rowid",
+        options.table.replace('"', "\"\"")
+    );
+    let columns = db::column_names(conn, &query)
+        .await
+        .wrap_err_with(|| format!("Failed to read `{}`", options.table))
+        .kind(ErrorKind::Db)?;
+    let mut rows = conn.fetch(query.as_str());
+    let mut writer = RowWriter::new(options.format, out);
+    while let Some(row) = rows.next().await {
+        let row = row
+            .wrap_err_with(|| format!("Failed to read `{}`", options.table))
+            .kind(ErrorKind::Db)?;
+        let mut row = ResultSet::from_rows(columns.clone(), std::slice::from_ref(&row))?;
+        if !options.include_raw {
+            row.hide_columns(&["raw".to_string()]);
+        }
+        writer.write(&row)?;
+    }
+    writer.finish()
+}
+
+/// Writes rows one at a time in an export format.
+enum RowWriter<W: Write> {
+    Csv {
+        writer: Box<csv::Writer<W>>,
+        wrote_header: bool,
+    },
+    Json {
+        out: W,
+        wrote_row: bool,
+    },
+}
+
+impl<W: Write> RowWriter<W> {
+    fn new(format: ExportFormat, out: W) -> Self {
+        match format {
+            ExportFormat::Csv => RowWriter::Csv {
+                writer: Box::new(csv::Writer::from_writer(out)),
+                wrote_header: false,
+            },
+            ExportFormat::Json => RowWriter::Json {
+                out,
+                wrote_row: false,
+            },
+        }
+    }
+
+    /// Write the rows of `results`, which all come from the same table.
+    fn write(&mut self, results: &ResultSet) -> Result<()> {
+        match self {
+            RowWriter::Csv {
+                writer,
+                wrote_header,
+            } => {
+                if !*wrote_header {
+                    writer.write_record(&results.columns)?;
+                    *wrote_header = true;
+                }
+                for row in &results.rows {
+                    writer.write_record(row.iter().map(ToString::to_string))?;
+                }
+            }
+            RowWriter::Json { out, wrote_row } => {
+                for values in &results.rows {
+                    out.write_all(if *wrote_row { b",\n" } else { b"[\n" })?;
+                    let row = JsonRow {
+                        columns: &results.columns,
+                        values,
+                        bool_columns: &[],
+                        raw_json: false,
+                    };
+                    serde_json::to_writer(&mut *out, &row)?;
+                    *wrote_row = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            RowWriter::Csv { mut writer, .. } => writer.flush()?,
+            RowWriter::Json { mut out, wrote_row } => {
+                out.write_all(if wrote_row { b"\n]\n" } else { b"[]\n" })?;
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::error;
+
+/// What pressing Ctrl-C does right now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OnCtrlC {
+    /// Exit immediately.
+    Exit,
+
+    /// Let the running query stop early and show what it has; a second press exits.
+    Stop,
+
+    /// Nothing, because the pager is in the foreground and handles it.
+    Ignore,
+}
+
+static ON_CTRL_C: AtomicU8 = AtomicU8::new(OnCtrlC::Exit as u8);
+
+/// Handle Ctrl-C for the rest of the process. Once tokio is listening for it, the default
+/// handler never runs, so this exits on its behalf unless something else is in charge.
+pub fn install() {
+    tokio::spawn(async {
+        loop {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+
+            // A press while stopping only stops; the next one exits.
+            let on_ctrl_c = ON_CTRL_C.swap(OnCtrlC::Exit as u8, Ordering::SeqCst);
+            if on_ctrl_c == OnCtrlC::Ignore as u8 {
+                ON_CTRL_C.store(on_ctrl_c, Ordering::SeqCst);
+            } else if on_ctrl_c == OnCtrlC::Exit as u8 {
+                // End the `^C` line so the shell prompt starts on its own.
+                eprintln!();
+                std::process::exit(error::EXIT_INTERRUPTED);
+            }
+        }
+    });
+}
+
+/// Set what Ctrl-C does until the next call.
+pub fn set(on_ctrl_c: OnCtrlC) {
+    ON_CTRL_C.store(on_ctrl_c as u8, Ordering::SeqCst);
+}
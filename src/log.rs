@@ -0,0 +1,94 @@
+use async_openai::types::Role;
+use eyre::{Context, Result};
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One invocation's exchange with the model, as written by `--log-json`.
+#[derive(Serialize)]
+pub struct LogRecord<'a> {
+    pub model: &'a str,
+
+    /// Every message sent to the model, in order.
+    #[serde(serialize_with = "serialize_prompt")]
+    pub prompt: &'a [(Role, String)],
+
+    /// Raw text of each completion, before any SQL extraction.
+    pub responses: &'a [String],
+
+    /// The SQL that was finally executed, if a candidate was chosen.
+    pub sql: Option<&'a str>,
+
+    pub row_count: Option<usize>,
+}
+
+fn serialize_prompt<S: serde::Serializer>(
+    prompt: &[(Role, String)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Message<'a> {
+        role: &'a Role,
+        content: &'a str,
+    }
+
+    serializer.collect_seq(
+        prompt
+            .iter()
+            .map(|(role, content)| Message { role, content }),
+    )
+}
+
+/// A summary of one invocation, as written by `--log-file`.
+#[derive(Serialize, Default)]
+pub struct Invocation {
+    /// When the invocation started, in RFC 3339 format (UTC).
+    pub timestamp: String,
+    pub question: Option<String>,
+    pub model: String,
+    pub generated_sql: Option<String>,
+    pub row_count: Option<usize>,
+    pub duration_ms: u128,
+    pub error: Option<String>,
+}
+
+/// The current time in RFC 3339 format, like `2024-02-29T13:05:09Z`.
+pub fn timestamp_now() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // Convert days since the epoch to a civil date (Howard Hinnant's `civil_from_days`).
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+/// Append `record` to the log at `path` as a single line of JSON. The line is written with one
+/// call on a file opened for appending, so concurrent invocations don't interleave records.
+pub fn append(path: &Path, record: &impl Serialize) -> Result<()> {
+    let mut line = serde_json::to_string(record).wrap_err("Failed to serialize log record")?;
+    line.push('\n');
+
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .wrap_err_with(|| format!("Failed to write log to {}", path.display()))
+}
@@ -1,232 +1,1345 @@
-use async_openai::types::Role;
 use async_openai::{config::OpenAIConfig, Client};
 use clap::Parser;
+use error::{ErrorKind, WithKind};
+use export::ExportFormat;
 use eyre::{Context, Result};
-use indoc::formatdoc;
-use sqlx::Column;
-use sqlx::ConnectOptions;
-use sqlx::Executor;
-use sqlx::Row;
-use std::str::FromStr;
+use render::{
+    BoolStyle, ColorScheme, CompactEnrollment, Expanded, Format, Hyperlinks, RenderOptions,
+    TableStyle,
+};
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::time::Duration;
+use terminal_size::Width;
+
+mod agent;
+mod batch;
+mod cache;
+mod db;
+mod diff;
+mod editor;
+mod error;
+mod export;
+mod interrupt;
+mod log;
+mod output;
+mod pager;
+mod prompt;
+mod render;
+mod saved;
+mod search;
+mod sql;
+mod stat;
+mod template;
+mod watch;
 
 #[derive(clap::Parser)]
+#[command(
+    group(clap::ArgGroup::new("row_template").args(["template", "template_file"])),
+    subcommand_negates_reqs = true,
+    after_help = error::EXIT_CODES_HELP
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[clap(short, long)]
     verbose: bool,
 
-    #[clap(long, default_value = "courses.sqlite3")]
-    db: String,
+    /// With `--verbose`, show at most N characters of each query.
+    #[clap(long, value_name = "N")]
+    truncate_sql: Option<usize>,
+
+    /// With `--verbose`, lay out each query over several indented lines.
+    #[clap(long)]
+    pretty_sql: bool,
+
+    /// Always show results in `$PAGER` (or `less -FRX`) when printing to a terminal. By default,
+    /// only results taller than the terminal are paged.
+    #[clap(long, global = true)]
+    pager: bool,
+
+    /// Never page results.
+    #[clap(long, global = true, conflicts_with = "pager")]
+    no_pager: bool,
+
+    /// DANGEROUS: open the database for writing, and run the model's SQL as-is, even if it isn't
+    /// a SELECT. Only use this on your own copy of the database.
+    #[clap(long)]
+    insecure_allow_writes: bool,
+
+    /// Only print the results (warnings and errors still go to stderr). Safe for pipelines.
+    #[clap(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Course database. Repeat to also attach others (like one per term) read-only, under names
+    /// taken from their filenames, so questions can span them.
+    #[clap(long, global = true, default_value = "courses.sqlite3")]
+    db: Vec<String>,
+
+    /// Course database as a `sqlite:` URL or `file:` URI, instead of --db, for options like
+    /// `file:courses.sqlite3?mode=ro&cache=shared`.
+    #[clap(long, global = true, value_name = "URL", conflicts_with = "db")]
+    db_url: Option<String>,
+
+    /// Wait up to this many seconds for a lock held by another process, like an ingest writing
+    /// the database, before failing with "database is locked". 0 fails right away.
+    #[clap(long, global = true, value_name = "N", default_value_t = 5)]
+    busy_timeout_secs: u64,
 
     #[clap(long, default_value = "gpt-4-turbo-preview")]
     model: String,
 
+    /// OpenAI API key.
+    #[clap(long, env = "OPENAI_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+
+    /// Base URL of an OpenAI-compatible API, like a local Ollama server. Doesn't require an API key.
+    #[clap(long, visible_alias = "base-url")]
+    api_base: Option<String>,
+
+    /// Give up on an OpenAI request after this many seconds. 0 waits forever.
+    #[clap(long, value_name = "N", default_value_t = 30)]
+    api_timeout_secs: u64,
+
+    /// Run this SQL instead of asking the model.
+    #[clap(long)]
+    sql: Option<String>,
+
+    /// Run the query again every N seconds, showing the new results in place of the old, until
+    /// Ctrl-C. The model is only asked once.
+    #[clap(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["all_candidates", "count", "crns_only", "csv_input", "output", "print_sql", "sql_only"]
+    )]
+    watch_interval: Option<u64>,
+
+    /// Show only the rows that changed since the last run of the same question, matched by CRN:
+    /// `+` for new rows, `-` for ones that are gone, and `~` for changed ones, with `old → new`
+    /// values. With --watch-interval, each refresh shows what changed since the one before.
+    #[clap(long, conflicts_with_all = ["all_candidates", "count", "crns_only", "csv_input"])]
+    diff: bool,
+
+    /// Print only the final SQL to stdout instead of the results, e.g. to pipe into `sqlite3`.
+    /// The query still runs, to pick the best candidate and check that it works, unless
+    /// --dry-run is given.
+    #[clap(
+        long,
+        conflicts_with_all = ["all_candidates", "explain", "csv_input", "diff", "output"]
+    )]
+    print_sql: bool,
+
+    /// With --print-sql, don't run the query. With several candidates, the first is printed,
+    /// since picking the best one needs their results.
+    #[clap(long, requires = "print_sql")]
+    dry_run: bool,
+
+    /// Shorthand for --print-sql --dry-run.
+    #[clap(
+        long,
+        conflicts_with_all = ["all_candidates", "explain", "csv_input", "diff", "output"]
+    )]
+    sql_only: bool,
+
+    /// JSON file of SQL for previously answered questions. Questions found in it skip the model,
+    /// and new answers are added to it.
+    #[clap(long)]
+    cache_file: Option<PathBuf>,
+
+    /// Save the question and its SQL under this name, to run again later with --run-query. The
+    /// SQL is saved as the model wrote it, before --count, --first, or --with-instructor.
+    #[clap(long, value_name = "NAME")]
+    save_query: Option<String>,
+
+    /// Run the SQL saved with --save-query under this name, without asking the model.
+    #[clap(long, value_name = "NAME", conflicts_with_all = ["sql", "save_query"])]
+    run_query: Option<String>,
+
+    /// Never call the API. Requires --sql, or a --cache-file entry for the question.
+    #[clap(long)]
+    offline: bool,
+
+    /// Number of SQL candidates to generate. The candidate returning the most rows is shown.
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=3))]
+    num_completions: u8,
+
+    /// Nucleus sampling: only consider tokens in the top P of probability mass, from 0 to 1.
+    /// Defaults to the API's default (1).
+    #[clap(long, value_name = "P", value_parser = parse_top_p)]
+    top_p: Option<f32>,
+
+    /// Penalize tokens that have appeared at all, from -2 to 2. Positive values push the model
+    /// toward new topics. Defaults to the API's default (0).
+    #[clap(long, value_parser = parse_penalty, allow_hyphen_values = true)]
+    presence_penalty: Option<f32>,
+
+    /// Penalize tokens by how often they've appeared, from -2 to 2. Positive values make
+    /// repetition less likely. Defaults to the API's default (0).
+    #[clap(long, value_parser = parse_penalty, allow_hyphen_values = true)]
+    frequency_penalty: Option<f32>,
+
+    /// Stop generating when the model writes this (`\n` means a newline). Repeat for up to 4
+    /// sequences; `--stop ''` turns them off. The default ends generation at a closing code fence,
+    /// cutting off any commentary after the query. If that leaves a response with no query (as
+    /// when the model wrote prose before the opening fence), the model is asked again without it.
+    #[clap(long, value_name = "SEQUENCE", default_value = "\\n```", value_parser = parse_stop)]
+    stop: Vec<String>,
+
+    /// Print the results of every candidate instead of picking one.
+    #[clap(long)]
+    all_candidates: bool,
+
+    /// Ask the model to answer with a JSON object (`{"sql": "..."}`), which is more robust to
+    /// extract than bare text. Responses that aren't JSON still fall back to text extraction.
+    #[clap(long)]
+    json_response: bool,
+
+    /// Add an `instructor` column with each section's instructor, joined on the query's `crn` and
+    /// `term`.
+    #[clap(long)]
+    with_instructor: bool,
+
+    /// After the query runs, add an `instructors` column with the names of every instructor of
+    /// each section (the first three, and how many more), looked up by the results' `id`, or
+    /// else their `crn` and `term`. Unlike --with-instructor, the query itself isn't changed.
+    #[clap(long, conflicts_with_all = ["all_candidates", "count", "with_instructor"])]
+    with_instructors: bool,
+
+    /// Before the results, print a plain-English explanation of the SQL. Costs an extra
+    /// completion.
+    #[clap(long, conflicts_with_all = ["offline", "quiet"])]
+    explain: bool,
+
+    /// Let the model ask a clarifying question when the question is ambiguous, instead of
+    /// guessing. The question is printed, and the exit code is 9.
+    #[clap(long)]
+    clarify: bool,
+
+    /// Include this many random rows from `sections` in the prompt, so the model can see what
+    /// values look like. Costs more tokens.
+    #[clap(long, default_value_t = 0)]
+    sample_rows: u32,
+
+    /// Describe the database to the model with the `schema_sql` key of this TOML or YAML file,
+    /// instead of the built-in course schema.
+    #[clap(long)]
+    schema_override: Option<PathBuf>,
+
+    /// Replace the built-in instructions (the first system message) with this file's contents.
+    /// `{{schema}}` in the file is replaced with the database schema.
+    #[clap(long)]
+    system_prompt_file: Option<PathBuf>,
+
+    /// Add an instruction for the model, like "prefer morning sections". Repeatable; each is sent
+    /// as its own system message after the schema, before the question.
+    #[clap(long, value_name = "TEXT")]
+    append_system: Vec<String>,
+
+    /// Write the prompt sent to the model to this file, as JSON.
+    #[clap(long)]
+    dump_prompt: Option<PathBuf>,
+
+    /// Send the prompt saved in this file by --dump-prompt, instead of building one.
+    #[clap(long)]
+    replay_prompt: Option<PathBuf>,
+
+    /// Context window of the model, in tokens. Larger prompts are rejected. Defaults to the
+    /// model's, if it's an OpenAI model, or 128000.
+    #[clap(long, value_name = "TOKENS")]
+    context_window: Option<usize>,
+
+    /// Truncate the schema prompt to fit the context window instead of failing.
+    #[clap(long)]
+    truncate_schema: bool,
+
+    /// Include online sections. By default, both the prompt and a filter on the results exclude
+    /// sections on the 'Online' campus and 'O' sections, which are online even on the Atlanta
+    /// campus.
+    #[clap(long)]
+    include_online: bool,
+
+    /// Sort rows by these columns (comma-separated). Add `:desc` to a column, or prefix it with
+    /// `-`, to sort it in descending order, like `--sort subject,seats_available:desc`. Numbers
+    /// sort numerically, text case-insensitively, and NULLs first. Applied after --dedup, and
+    /// before --group-by, which keeps this order within each group.
+    #[clap(long, value_delimiter = ',', allow_hyphen_values = true)]
+    sort: Vec<String>,
+
+    /// Sort rows by these columns (comma-separated) and show each group under a heading, like
+    /// `--group-by subject,number`. Only tables and JSON are grouped; other formats are just
+    /// sorted.
+    #[clap(long, value_delimiter = ',')]
+    group_by: Vec<String>,
+
+    /// Turn each distinct value of this column into a column of its own, with one row for each
+    /// distinct set of the other columns' values, and the number of rows with that value in each
+    /// cell. Applied after --hide-columns and --show-columns, like `--show-columns
+    /// subject,schedule_type --pivot schedule_type` for the number of sections of each type in
+    /// each subject.
+    #[clap(long, value_name = "COLUMN", conflicts_with_all = ["count", "diff"])]
+    pivot: Option<String>,
+
+    /// With --pivot, fill the cells with this column's values instead of counts. Numbers in the
+    /// same cell are summed, and other values joined with commas.
+    #[clap(long, value_name = "COLUMN", requires = "pivot")]
+    pivot_value: Option<String>,
+
+    /// Only print how many rows the query returns (as `{"count": N}` with JSON output). The count
+    /// is done in SQL, so it's exact and rows aren't fetched at all. `--assert-nonempty` and
+    /// `--assert-max-rows` check the count.
+    #[clap(
+        long,
+        visible_alias = "count-only",
+        conflicts_with_all = ["insecure_allow_writes", "output"]
+    )]
+    count: bool,
+
+    /// Only print the distinct CRNs in the results, one per line, for pasting into registration.
+    /// Like rows, only the first --limit are printed, with a note on stderr if any are left out.
+    #[clap(long, conflicts_with_all = ["count", "output"])]
+    crns_only: bool,
+
+    /// Separator between CRNs with `--crns-only`, like `,` (the default is a newline).
+    #[clap(long, value_name = "SEPARATOR", requires = "crns_only")]
+    crn_separator: Option<String>,
+
+    /// Only fetch the first N rows, by wrapping the query in a `LIMIT`.
+    #[clap(long, value_name = "N", conflicts_with = "last")]
+    first: Option<usize>,
+
+    /// Only show the last N rows. Every row is still fetched.
+    #[clap(long, value_name = "N")]
+    last: Option<usize>,
+
+    /// Only keep the first row with each value of this column, like the unique subjects.
+    #[clap(long, value_name = "COLUMN")]
+    distinct_column: Option<String>,
+
+    /// Merge rows with the same CRN (like cross-listed sections), listing every subject.
+    #[clap(long)]
+    dedup: bool,
+
+    /// Only show these columns of the results, in this order (comma-separated). Names are
+    /// case-insensitive.
+    #[clap(long, visible_alias = "columns", value_delimiter = ',')]
+    show_columns: Vec<String>,
+
+    /// Don't show these columns of the results (comma-separated, or repeat the flag). Names are
+    /// case-insensitive. By default, the `raw` column of scraped source data is hidden (see
+    /// --show-raw); giving any columns replaces that default (so list `raw` too to keep it
+    /// hidden), and `--hide-columns ''` shows everything.
+    #[clap(
+        long,
+        visible_alias = "hide-column",
+        value_delimiter = ',',
+        default_value = "raw"
+    )]
+    hide_columns: Vec<String>,
+
+    /// Add a leading `#` column numbering the result rows from 1.
+    #[clap(long)]
+    row_numbers: bool,
+
+    /// Fail with exit code 1 if the query returns no rows.
+    #[clap(long)]
+    assert_nonempty: bool,
+
+    /// Fail with exit code 1 if the query returns more than this many rows.
+    #[clap(long)]
+    assert_max_rows: Option<usize>,
+
+    /// Output format for query results. Defaults to `table` on a terminal, and `tsv` when stdout
+    /// is piped.
+    #[clap(long, value_enum)]
+    format: Option<Format>,
+
+    /// With TSV output, separate fields with this character instead of a tab. Escapes like `\t`
+    /// and `\|` are accepted.
+    #[clap(long, value_parser = parse_field_separator)]
+    field_separator: Option<char>,
+
+    /// Word-wrap table cells to this many characters. 0 disables wrapping.
+    #[clap(long, default_value_t = 0)]
+    wrap_width: usize,
+
+    /// Truncate table and markdown cells longer than this, with an ellipsis. 0 disables.
+    #[clap(long, default_value_t = 0)]
+    max_col_width: usize,
+
+    /// Maximum width of the table: a number of columns, or `auto` for the terminal width (the
+    /// default; `$COLUMNS`, or unlimited, when stdout isn't a terminal). The widest text columns
+    /// are shrunk until it fits.
+    #[clap(long, visible_alias = "max-table-width", value_parser = parse_max_width)]
+    max_width: Option<MaxWidth>,
+
+    /// Don't color table rows by seat availability. Color is also off when stdout isn't a
+    /// terminal, `NO_COLOR` is set, or `TERM=dumb`.
+    #[clap(long)]
+    no_color: bool,
+
+    /// Don't highlight the question's words (other than common ones like "the" or "classes")
+    /// where they appear in table cells. Highlighting is only done along with color.
+    #[clap(long)]
+    no_highlight: bool,
+
+    /// Colors for open, waitlisted, and closed sections.
+    #[clap(long, value_enum, default_value_t = ColorScheme::Default)]
+    color_scheme: ColorScheme,
+
+    /// Collapse enrollment and waitlist columns into one `seats` column, like
+    /// `87/110 (23 open, WL 5/10)`. By default, only in tables, markdown, and HTML; with
+    /// `--compact-enrollment=all`, in every format.
+    #[clap(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "display"
+    )]
+    compact_enrollment: Option<CompactEnrollment>,
+
+    /// Columns holding booleans, shown as ✓/✗ in tables and as booleans in JSON
+    /// (comma-separated).
+    #[clap(long, value_delimiter = ',', default_value = "open")]
+    bool_columns: Vec<String>,
+
+    /// How boolean columns are shown in tables: ✓/✗ symbols (Y/N with --ascii), or Yes/No words.
+    #[clap(long, value_enum, default_value_t = BoolStyle::Symbols)]
+    bool_style: BoolStyle,
+
+    /// Show booleans in tables as Y/N instead of ✓/✗, and default to ASCII table borders.
+    #[clap(long)]
+    ascii: bool,
+
+    /// Show attribute codes like `ETHS,HUM` as names ("Ethics, Humanities") in markdown and HTML
+    /// too, not just tables. JSON and CSV keep the codes.
+    #[clap(long)]
+    expand_attributes: bool,
+
+    /// Link CRNs in tables to their sections' pages, in terminals that support it. Needs the
+    /// results to have `crn` and `term` columns.
+    #[clap(long, value_enum, default_value_t)]
+    hyperlinks: Hyperlinks,
+
+    /// URL for `--hyperlinks`, with `{term}` and `{crn}` standing for the section's.
+    #[clap(long, default_value = render::DEFAULT_HYPERLINK_URL)]
+    hyperlink_url: String,
+
+    /// Show attribute codes as stored, even in tables.
+    #[clap(long, conflicts_with = "expand_attributes")]
+    no_expand_attributes: bool,
+
+    /// TOML or YAML file of names for attribute codes, like `ETHS = "Ethics"`, adding to (or
+    /// replacing) the built-in ones. The names are also given to the model.
+    #[clap(long, value_name = "PATH", value_parser = parse_attributes_file)]
+    attributes_file: Option<AttributeNames>,
+
+    /// Show values exactly as stored in the database, e.g. 'true' rather than ✓.
+    #[clap(long)]
+    raw_values: bool,
+
+    /// Show the `raw` column of scraped source data, which is hidden by default, as formatted
+    /// JSON. JSON output embeds it as an object rather than a string.
+    #[clap(long)]
+    show_raw: bool,
+
+    /// Elide `raw` JSON nested deeper than this in table, markdown, and HTML cells. Expanded
+    /// records always show all of it. 0 shows everything.
+    #[clap(long, value_name = "N", default_value_t = 2, requires = "show_raw")]
+    raw_depth: usize,
+
+    /// Print each row as a block of `column | value` lines instead of a table, like psql's `\x`.
+    /// With `--expanded=auto`, only when the table would be too wide.
+    #[clap(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "always"
+    )]
+    expanded: Option<Expanded>,
+
+    /// Borders for tables. Defaults to rounded at a terminal, and ASCII otherwise or with
+    /// --ascii. Set GT_REG_TABLE_STYLE to change the default.
+    #[clap(long, value_enum, env = "GT_REG_TABLE_STYLE")]
+    table_style: Option<TableStyle>,
+
+    /// Word-wrap columns that are shrunk to fit the table width.
+    #[clap(long, conflicts_with = "truncate")]
+    wrap: bool,
+
+    /// Truncate columns that are shrunk to fit the table width, with an ellipsis (the default).
+    #[clap(long)]
+    truncate: bool,
+
+    /// Print each row with this handlebars template instead of --format, like
+    /// `{{subject}} {{number}} (CRN {{crn}}): {{seats_available}} seats`. Helpers: `pad value
+    /// width` and `lpad value width` pad with spaces, and `default value fallback` fills in NULLs.
+    #[clap(long)]
+    template: Option<String>,
+
+    /// Read the --template from this file.
+    #[clap(long, value_name = "PATH")]
+    template_file: Option<PathBuf>,
+
+    /// Template for a line before the templated rows. `{{row_count}}` is the number of rows.
+    #[clap(long, requires = "row_template")]
+    template_header: Option<String>,
+
+    /// Template for a line after the templated rows. `{{row_count}}` is the number of rows.
+    #[clap(long, requires = "row_template")]
+    template_footer: Option<String>,
+
+    /// Also write the results to this file.
+    #[clap(long)]
+    output: Option<PathBuf>,
+
+    /// Format for --output. Defaults to --format, or `table`.
+    #[clap(long, value_enum, requires = "output")]
+    output_format: Option<Format>,
+
+    /// Create --output's parent directories if they don't exist.
+    #[clap(long, requires = "output")]
+    mkdirs: bool,
+
+    /// Overwrite --output if it already exists.
+    #[clap(long, requires = "output")]
+    force: bool,
+
+    /// With HTML output, embed a default stylesheet.
+    #[clap(long)]
+    html_style: bool,
+
+    /// With HTML output, write a complete standalone document instead of just the table.
+    #[clap(long)]
+    html_full_page: bool,
+
+    /// Leave out the header row (e.g. to append to an existing file, or pipe into `wc -l`). JSON
+    /// has no header, so is unaffected. Not for markdown, whose tables need one.
+    #[clap(long)]
+    no_header: bool,
+
+    /// With JSON output, wrap the rows in an object along with the SQL and row count.
+    #[clap(long)]
+    json_envelope: bool,
+
+    /// Show only the first N rows, noting how many were left out. 0 shows every row.
+    #[clap(long, global = true, value_name = "N", default_value_t = 500)]
+    limit: usize,
+
+    /// Append a JSON record of the prompt, model response, and final SQL to this file.
+    #[clap(long)]
+    log_json: Option<PathBuf>,
+
+    /// Append a JSON line summarizing this invocation (question, SQL, row count, duration, and
+    /// any error) to this file.
+    #[clap(long)]
+    log_file: Option<PathBuf>,
+
+    /// Answer each question in this CSV file (with a `question` header) instead of a single
+    /// question. Requires --output-dir. Results are written as CSV unless --format picks another
+    /// file format; `table` isn't one.
+    #[clap(long, requires = "output_dir")]
+    csv_input: Option<PathBuf>,
+
+    /// Directory to write numbered result files to, one per question in --csv-input.
+    #[clap(long)]
+    output_dir: Option<PathBuf>,
+
+    /// Write the question in `$EDITOR` instead of on the command line.
+    #[clap(long)]
+    edit: bool,
+
     /// Question to answer based on the course database.
-    question: String,
+    #[clap(
+        required_unless_present_any = ["csv_input", "sql", "edit", "replay_prompt", "run_query"],
+        env = "GT_REG_QUESTION"
+    )]
+    question: Option<String>,
+}
+
+impl Args {
+    /// The `--db-url`, or else the first `--db`, which subcommands operate on.
+    fn main_db(&self) -> &str {
+        self.db_url.as_deref().unwrap_or(&self.db[0])
+    }
+
+    /// How long to wait for another process's lock on the database.
+    fn busy_timeout(&self) -> Duration {
+        Duration::from_secs(self.busy_timeout_secs)
+    }
+
+    /// Names of attribute codes: the built-in ones, plus any from `--attributes-file`.
+    fn attribute_names(&self) -> Vec<(String, String)> {
+        let extra = self
+            .attributes_file
+            .as_ref()
+            .map(|AttributeNames(names)| names.as_slice());
+        render::attribute_names(extra.unwrap_or_default())
+    }
+
+    /// The format to print results to stdout in.
+    fn effective_format(&self) -> Format {
+        render::effective_format(self.format, std::io::stdout().is_terminal())
+    }
+
+    fn paging(&self) -> pager::Paging {
+        if self.pager {
+            pager::Paging::Always
+        } else if self.no_pager {
+            pager::Paging::Never
+        } else {
+            pager::Paging::Auto
+        }
+    }
+
+    /// Whether to color results on stdout.
+    fn color_enabled(&self) -> bool {
+        !self.no_color && render::color_enabled(&std::io::stdout())
+    }
+
+    /// The --template or --template-file, compiled.
+    fn row_template(&self) -> Result<Option<template::RowTemplate>> {
+        let source = match (&self.template, &self.template_file) {
+            (Some(template), _) => template.clone(),
+            (None, Some(path)) => std::fs::read_to_string(path)
+                .wrap_err_with(|| format!("Failed to read template from {}", path.display()))
+                .kind(ErrorKind::Usage)?,
+            (None, None) => return Ok(None),
+        };
+        let template = template::RowTemplate::new(
+            source.trim_end_matches('\n'),
+            self.template_header.as_deref(),
+            self.template_footer.as_deref(),
+        )?;
+        Ok(Some(template))
+    }
+
+    /// The token budget for prompts: `--context-window`, or the model's.
+    fn context_window(&self) -> usize {
+        self.context_window
+            .or_else(|| prompt::context_window(&self.model))
+            .unwrap_or(128000)
+    }
+
+    fn render_options(&self) -> RenderOptions {
+        let format = self.effective_format();
+        RenderOptions {
+            format,
+            json_envelope: self.json_envelope,
+            no_header: self.no_header,
+            limit: self.limit,
+            field_separator: self.field_separator,
+            wrap_width: self.wrap_width,
+            max_col_width: self.max_col_width,
+            max_width: match self.max_width {
+                Some(MaxWidth::Columns(width)) => Some(width),
+                Some(MaxWidth::Auto) | None => terminal_width(),
+            },
+            wrap_to_fit: self.wrap,
+            expanded: self.expanded.unwrap_or_default(),
+            table_style: render::effective_table_style(
+                self.table_style,
+                self.ascii,
+                std::io::stdout().is_terminal(),
+            ),
+            color: self.color_enabled().then_some(self.color_scheme),
+            highlight_terms: match &self.question {
+                Some(question) if self.color_enabled() && !self.no_highlight => {
+                    render::highlight_terms(question)
+                }
+                _ => vec![],
+            },
+            compact_enrollment: self.compact_enrollment,
+            group_by: self.group_by.clone(),
+            bool_columns: self.bool_columns.clone(),
+            bool_style: self.bool_style,
+            ascii: self.ascii,
+            expand_attributes: !self.no_expand_attributes
+                && !self.raw_values
+                && (self.expand_attributes || format == Format::Table),
+            attribute_names: self.attribute_names(),
+            hyperlink_url: match self.hyperlinks {
+                Hyperlinks::On => true,
+                Hyperlinks::Off => false,
+                Hyperlinks::Auto => render::hyperlinks_supported(&std::io::stdout()),
+            }
+            .then(|| self.hyperlink_url.clone()),
+            raw_depth: (self.show_raw && !self.raw_values).then_some(self.raw_depth),
+            raw_values: self.raw_values,
+            html_details: self.verbose,
+            html_style: self.html_style,
+            html_full_page: self.html_full_page,
+        }
+    }
+}
+
+/// Width of the terminal on stdout. When that's unknown (stdout isn't a terminal, or it reports
+/// a width of zero, as some CI environments do), `$COLUMNS` is used if set. Otherwise there's no
+/// limit, so output captured to a file isn't squeezed to some arbitrary width.
+fn terminal_width() -> Option<usize> {
+    terminal_size::terminal_size()
+        .map(|(Width(width), _)| width as usize)
+        .filter(|&width| width > 0)
+        .or_else(|| {
+            let columns: usize = std::env::var("COLUMNS").ok()?.parse().ok()?;
+            (columns > 0).then_some(columns)
+        })
+}
+
+/// Value of `--attributes-file`: the names it gives, as (code, name) pairs.
+#[derive(Debug, Clone)]
+struct AttributeNames(Vec<(String, String)>);
+
+fn parse_attributes_file(path: &str) -> Result<AttributeNames, String> {
+    prompt::load_attribute_names(path.as_ref())
+        .map(AttributeNames)
+        .map_err(|err| format!("{err:#}"))
+}
+
+/// Value of `--max-width`.
+#[derive(Debug, Clone, Copy)]
+enum MaxWidth {
+    Auto,
+    Columns(usize),
+}
+
+fn parse_stop(value: &str) -> Result<String, String> {
+    Ok(value.replace("\\n", "\n"))
+}
+
+fn parse_top_p(value: &str) -> Result<f32, String> {
+    parse_in_range(value, 0.0..=1.0)
+}
+
+fn parse_penalty(value: &str) -> Result<f32, String> {
+    parse_in_range(value, -2.0..=2.0)
+}
+
+fn parse_in_range(value: &str, range: std::ops::RangeInclusive<f32>) -> Result<f32, String> {
+    let number: f32 = value
+        .parse()
+        .map_err(|_| format!("`{value}` isn't a number"))?;
+    if !range.contains(&number) {
+        return Err(format!(
+            "must be between {} and {}",
+            range.start(),
+            range.end()
+        ));
+    }
+    Ok(number)
+}
+
+fn parse_max_width(value: &str) -> Result<MaxWidth, String> {
+    if value == "auto" {
+        return Ok(MaxWidth::Auto);
+    }
+    value
+        .parse()
+        .map(MaxWidth::Columns)
+        .map_err(|_| "expected a number of columns, or `auto`".to_string())
+}
+
+fn parse_field_separator(value: &str) -> Result<char, String> {
+    let unescaped = match value {
+        "\\t" => "\t",
+        "\\n" | "\\r" => return Err("newlines can't separate fields".to_string()),
+        _ => value.strip_prefix('\\').unwrap_or(value),
+    };
+
+    let mut chars = unescaped.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c != '\n' && c != '\r' => Ok(c),
+        _ => Err("expected a single character, like `|` or `\\t`".to_string()),
+    }
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Create an empty course database with the expected schema and full-text index.
+    Init {
+        /// Replace the database file if it already exists.
+        #[clap(long)]
+        overwrite: bool,
+    },
+
+    /// Upgrade an existing course database.
+    Migrate {
+        /// Add the full-text index used by `search`. SQLite must be built with FTS5.
+        #[clap(long)]
+        add_fts: bool,
+    },
+
+    /// Print summary statistics about the course database.
+    Stat,
+
+    /// Search course titles for a keyword, without asking the model.
+    Search {
+        /// Text to look for in course titles.
+        keyword: String,
+
+        /// Only show sections in this subject, like 'CS'.
+        #[clap(long)]
+        subject: Option<String>,
+
+        /// Only show sections in this term, like '202402' or 'Spring 2024'.
+        #[clap(long)]
+        term: Option<String>,
+
+        /// Only show sections that are open for registration.
+        #[clap(long)]
+        open_only: bool,
+
+        /// Output format, as for questions. Not global, since `export` has a `--format` of its
+        /// own.
+        #[clap(long, value_enum)]
+        format: Option<Format>,
+    },
+
+    /// Dump a whole table to CSV or JSON, without asking the model.
+    Export {
+        #[clap(long, value_enum, default_value_t)]
+        format: ExportFormat,
+
+        /// Table to dump.
+        #[clap(long, default_value = "sections")]
+        table: String,
+
+        /// Include the `raw` column of scraped source data.
+        #[clap(long)]
+        include_raw: bool,
+
+        /// Write to this file instead of stdout.
+        #[clap(long)]
+        output: Option<PathBuf>,
+
+        /// Overwrite --output if it already exists.
+        #[clap(long, requires = "output")]
+        force: bool,
+    },
+
+    /// List the queries saved with --save-query.
+    ListQueries,
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
+    error::install();
     let args = Args::parse();
+    interrupt::install();
 
-    // Open a read-only sqlite connection
-    let mut conn = sqlx::sqlite::SqliteConnectOptions::from_str(&args.db)
-        .wrap_err("Invalid db connection string")?
-        .read_only(true)
-        .connect()
-        .await?;
-
-    // Make an OpenAI client.
-    let oai_config = OpenAIConfig::default();
-    let oai_client = Client::with_config(oai_config);
-
-    let mut prompt: Vec<(Role, String)> = vec![];
-
-    prompt.push((
-        Role::System,
-        formatdoc! {r#"
-            You are an agent designed to help students with course registration at Georgia Tech. You have access to a SQLite database of available sections to register. Your job is to write a query against that database to answer a student's question about course registration. You should be very selective about the columns you select from the database---only include important information to answer the question. Always include a CRN, if it makes sense to do so. Do NOT include enrollment information if the user doesn't ask for it.
-
-            Assume this student is enrolled in the Atlanta campus, and only interested in courses they can register for in-person.
-
-            If a student refers to a course like 'CS 1331', they are referring to the course number, '1331' and subject 'CS'. If a student refers to 'CS 8803 ANI', they're refering to the 'ANI' section of CS 8803.
-
-            Here is the schema of the database:
-            ```sql
-            {DB_INFO_PROMPT}
-            ```
-
-            The next message will have a question from a student. Read it carefully:
-        "#},
-    ));
-
-    prompt.push((Role::User, args.question.clone()));
-
-    prompt.push((
-        Role::System,
-        "Given the following question, write a single SQL query to answer it. Take a deep breath and think carefully before responding. Respond ONLY with the text of the SQL query, or else it won't work and the student will be very sad.".to_string(),
-    ));
-
-    // Build the OpenAI request.
-    let chat_completion_request = async_openai::types::CreateChatCompletionRequest {
-        model: args.model.to_string(),
-        messages: prompt
-            .into_iter()
-            .map(
-                |(role, content)| async_openai::types::ChatCompletionRequestMessage {
-                    role,
-                    content: Some(content),
-                    ..Default::default()
-                },
-            )
-            .collect(),
+    let started = std::time::Instant::now();
+    let log_file = args.log_file.clone();
+    let mut invocation = log::Invocation {
+        timestamp: log::timestamp_now(),
+        question: args.question.clone(),
+        model: args.model.clone(),
         ..Default::default()
     };
 
-    let response = oai_client
-        .chat()
-        .create(chat_completion_request)
-        .await
-        .wrap_err("Failed to open result stream from OpenAI")?;
+    let result = run(args, &mut invocation).await;
+
+    if let Some(log_file) = log_file {
+        invocation.duration_ms = started.elapsed().as_millis();
+        invocation.error = result.as_ref().err().map(|err| format!("{err:#}"));
+        if let Err(err) = log::append(&log_file, &invocation) {
+            eprintln!("warning: {err:#}");
+        }
+    }
+
+    match result {
+        Ok(code) => std::process::exit(code),
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            std::process::exit(error::exit_code(&err));
+        }
+    }
+}
+
+/// Run the tool, returning the exit code. The SQL and row count are recorded in `invocation`.
+async fn run(mut args: Args, invocation: &mut log::Invocation) -> Result<i32> {
+    if args.sql_only {
+        args.print_sql = true;
+        args.dry_run = true;
+    }
+
+    if let Some(Command::Search {
+        format: Some(format),
+        ..
+    }) = args.command
+    {
+        args.format = Some(format);
+    }
+
+    // A markdown table without its header row isn't a table at all.
+    let file_format = args
+        .output
+        .is_some()
+        .then(|| args.output_format.or(args.format));
+    let markdown = args.effective_format() == Format::Markdown
+        || file_format.is_some_and(|format| format.unwrap_or_default() == Format::Markdown);
+    if args.no_header && markdown {
+        return Err(eyre::eyre!(
+            "Markdown tables need a header row; drop --no-header"
+        ))
+        .kind(ErrorKind::Usage);
+    }
+
+    match &args.command {
+        Some(Command::Init { overwrite }) => {
+            db::init(args.main_db(), *overwrite)
+                .await
+                .kind(ErrorKind::Db)?;
+            return Ok(0);
+        }
+        Some(Command::Migrate { add_fts }) => {
+            if !add_fts {
+                return Err(eyre::eyre!(
+                    "Nothing to do; pass --add-fts to add the full-text index"
+                ))
+                .kind(ErrorKind::Usage);
+            }
+            db::add_fts(args.main_db(), args.busy_timeout())
+                .await
+                .kind(ErrorKind::Db)?;
+            return Ok(0);
+        }
+        Some(Command::Stat) => {
+            let mut conn = db::open(args.main_db(), true, args.busy_timeout())
+                .await
+                .kind(ErrorKind::Db)?;
+            stat::print(&mut conn, &args.render_options()).await?;
+            return Ok(0);
+        }
+        Some(Command::Export {
+            format,
+            table,
+            include_raw,
+            output,
+            force,
+        }) => {
+            if let Some(path) = output {
+                output::check_path(path, *force, false).kind(ErrorKind::Usage)?;
+            }
+            let mut conn = db::open(args.main_db(), true, args.busy_timeout())
+                .await
+                .kind(ErrorKind::Db)?;
+            let options = export::ExportOptions {
+                table,
+                format: *format,
+                include_raw: *include_raw,
+            };
+            match output {
+                Some(path) => {
+                    let file = std::fs::File::create(path)
+                        .wrap_err_with(|| format!("Failed to create {}", path.display()))?;
+                    export::export(&mut conn, &options, std::io::BufWriter::new(file)).await?;
+                }
+                None => export::export(&mut conn, &options, std::io::stdout().lock()).await?,
+            }
+            return Ok(0);
+        }
+        Some(Command::Search {
+            keyword,
+            subject,
+            term,
+            open_only,
+            format: _,
+        }) => {
+            let mut conn = db::open(args.main_db(), true, args.busy_timeout())
+                .await
+                .kind(ErrorKind::Db)?;
+            let options = search::SearchOptions {
+                keyword,
+                subject: subject.as_deref(),
+                term: term.as_deref(),
+                open_only: *open_only,
+            };
+            let results = search::search(&mut conn, &options)
+                .await
+                .kind(ErrorKind::Db)?;
+            let render_options = args.render_options();
+            pager::print(
+                &render::render(&results, keyword, "", &render_options)?,
+                args.paging(),
+            );
+            // CSV and TSV have no footer, as with questions.
+            let omitted = results.rows.len().saturating_sub(render_options.limit);
+            let csv_or_tsv = matches!(render_options.format, Format::Csv | Format::Tsv);
+            if render_options.limit > 0 && omitted > 0 && csv_or_tsv && !args.quiet {
+                eprintln!("{}", render::more_rows_footer(omitted).trim_start());
+            }
 
-    // Get the query from the response text.
-    let response_text = response.choices[0].message.content.as_ref().unwrap().trim();
+            if results.rows.is_empty() {
+                return Ok(error::EXIT_NO_ROWS);
+            }
+            return Ok(0);
+        }
+        Some(Command::ListQueries) => {
+            let queries = saved::load()?;
+            if queries.is_empty() {
+                eprintln!("No saved queries; save one with --save-query <NAME>.");
+                return Ok(error::EXIT_NO_ROWS);
+            }
+            let results = render::ResultSet {
+                columns: vec!["name".into(), "question".into(), "sql".into()],
+                rows: queries
+                    .into_iter()
+                    .map(|(name, query)| {
+                        vec![
+                            render::Value::Text(name),
+                            render::Value::Text(query.question),
+                            render::Value::Text(query.sql),
+                        ]
+                    })
+                    .collect(),
+            };
+            pager::print(
+                &render::table(&results, &args.render_options()),
+                args.paging(),
+            );
+            return Ok(0);
+        }
+        None => {}
+    }
+
+    // Run a saved query as if its SQL had been passed with `--sql`.
+    if let Some(name) = &args.run_query {
+        let query = saved::get(name)?;
+        args.question.get_or_insert(query.question);
+        args.sql = Some(query.sql);
+    }
 
-    // Strip lines starting with "```"
-    let response_text = response_text
-        .lines()
-        .filter(|line| !line.trim().starts_with("```"))
-        .collect::<Vec<_>>()
-        .join("\n");
+    // Fail fast if we won't be able to talk to OpenAI, rather than with a 401 later on.
+    let needs_api = (!args.offline && args.sql.is_none()) || args.explain;
+    if needs_api && args.api_base.is_none() && args.api_key.as_deref().is_none_or(str::is_empty) {
+        eprintln!(
+            "error: OPENAI_API_KEY is not set; export it or pass --base-url for a local model."
+        );
+        return Ok(error::EXIT_NO_API_KEY);
+    }
 
-    if args.verbose {
-        eprintln!("{response_text}");
+    // Check the output file before doing any work we'd have to throw away.
+    if let Some(path) = &args.output {
+        output::check_path(path, args.force, args.mkdirs).kind(ErrorKind::Usage)?;
     }
+    let row_template = args.row_template()?;
 
-    // Run the SQL.
-    let rows = conn
-        .fetch_all(response_text.as_str())
+    // Open a sqlite connection, read-only by default. This happens before any network call, so a bad
+    // `--db` fails instantly.
+    if args.insecure_allow_writes {
+        eprintln!(
+            "warning: --insecure-allow-writes is set; the model's SQL can modify the database"
+        );
+    }
+    let mut conn = db::open(
+        args.main_db(),
+        !args.insecure_allow_writes,
+        args.busy_timeout(),
+    )
+    .await
+    .kind(ErrorKind::Db)?;
+    db::attach(&mut conn, &args.db[1..])
         .await
-        .wrap_err("Failed to execute SQL query")?;
+        .kind(ErrorKind::Db)?;
+
+    // Make an OpenAI client.
+    let mut oai_config = OpenAIConfig::default();
+    if let Some(api_key) = &args.api_key {
+        oai_config = oai_config.with_api_key(api_key);
+    }
+    if let Some(api_base) = &args.api_base {
+        oai_config = oai_config.with_api_base(api_base);
+    }
+    // Rate limits are retried by `agent::create_completion`, which reports each retry, so turn
+    // off the client's own silent retries.
+    let no_retries = backoff::ExponentialBackoffBuilder::new()
+        .with_max_elapsed_time(Some(Duration::ZERO))
+        .build();
+    let oai_client = Client::with_config(oai_config).with_backoff(no_retries);
 
-    let mut table = term_table::Table::new();
-    table.style = term_table::TableStyle::rounded();
-    table.separate_rows = true;
+    if let Some(csv_input) = &args.csv_input {
+        batch::run(&args, &mut conn, &oai_client, csv_input).await?;
+        return Ok(0);
+    }
 
-    let mut header_row = vec![];
+    // Kept in `args`, where the results' highlighting finds it.
+    if args.edit {
+        let edited = editor::question().kind(ErrorKind::Usage)?;
+        invocation.question = Some(edited.clone());
+        args.question = Some(edited);
+    }
+    // The question is optional with `--sql`.
+    let question = args.question.as_deref().unwrap_or_default();
+    let candidates = agent::run_candidates(&args, &mut conn, &oai_client, question).await?;
 
-    // Print the results.
-    for row in rows {
-        let mut tab_row = vec![];
-        header_row.clear();
-        for column in row.columns() {
-            if column.name() == "raw" {
-                continue;
+    if let Some(clarification) = &candidates.clarification {
+        if let Some(log_path) = &args.log_json {
+            log::append(log_path, &candidates.log_record(&args.model, None))?;
+        }
+        println!("{clarification}");
+        return Ok(error::EXIT_CLARIFY);
+    }
+
+    if args.dry_run {
+        if let Some(log_path) = &args.log_json {
+            log::append(
+                log_path,
+                &candidates.log_record(&args.model, Some((0, None))),
+            )?;
+        }
+        invocation.generated_sql = Some(candidates.sql[0].clone());
+        println!("{}", candidates.sql[0]);
+        return Ok(0);
+    }
+
+    let render_options = args.render_options();
+    if args.format.is_none() && render_options.format != Format::Table && !args.quiet {
+        note_auto_format();
+    }
+
+    if args.all_candidates {
+        if let Some(log_path) = &args.log_json {
+            log::append(log_path, &candidates.log_record(&args.model, None))?;
+        }
+
+        for (i, (sql, result)) in candidates.sql.iter().zip(candidates.results).enumerate() {
+            // Keep labels off stdout when it's meant to be machine-readable.
+            if args.quiet {
+                // No labels at all.
+            } else if render_options.format == render::Format::Table {
+                println!("Candidate {}:", i + 1);
+            } else {
+                eprintln!("Candidate {}:", i + 1);
+            }
+            match result {
+                Ok(rows) => {
+                    let results = agent::prepare_results(&args, &mut conn, rows).await?;
+                    println!(
+                        "{}",
+                        render::render(&results, question, sql, &render_options)?
+                    );
+                }
+                Err(err) => eprintln!("{err:?}\n"),
+            }
+        }
+        if candidates.interrupted {
+            eprintln!("(interrupted; later candidates weren't run)");
+            return Ok(error::EXIT_INTERRUPTED);
+        }
+        return Ok(0);
+    }
+
+    let mut answer = agent::pick_best(&args, &mut conn, candidates).await?;
+    invocation.generated_sql = Some(answer.sql.clone());
+    invocation.row_count = Some(answer.results.rows.len());
+    if let (Some(path), None) = (&args.cache_file, &args.sql) {
+        cache::store(path, question, &answer.model_sql)?;
+    }
+    if let Some(name) = &args.save_query {
+        let query = saved::SavedQuery {
+            question: question.to_string(),
+            sql: answer.model_sql.clone(),
+        };
+        saved::save(name, query)?;
+        if !args.quiet {
+            eprintln!("Saved query `{name}`.");
+        }
+    }
+
+    if args.print_sql {
+        println!("{}", answer.sql);
+        return Ok(0);
+    }
+
+    if args.explain {
+        let explanation = agent::explain(&args, &oai_client, question, &answer.sql).await?;
+        // Like candidate labels, keep it off stdout when that's meant to be machine-readable.
+        if render_options.format == Format::Table {
+            println!("{explanation}\n");
+        } else {
+            eprintln!("{explanation}\n");
+        }
+    }
+
+    if args.diff && !answer.results.columns.iter().any(|c| c == "crn") {
+        return Err(eyre::eyre!(
+            "--diff matches rows by CRN, but the results have no `crn` column. Columns: {}",
+            answer.results.columns.join(", ")
+        ))
+        .kind(ErrorKind::Usage);
+    }
+
+    // Stopping the first run with Ctrl-C stops watching, too.
+    if let (Some(interval), false) = (args.watch_interval, answer.interrupted) {
+        return watch::run(
+            &args,
+            &mut conn,
+            question,
+            answer,
+            Duration::from_secs(interval),
+        )
+        .await;
+    }
+
+    // Show only what changed since the last run of the question.
+    if args.diff {
+        let key = if question.trim().is_empty() {
+            &answer.sql
+        } else {
+            question
+        };
+        let previous = diff::swap(key, &answer.results)?;
+        match previous.and_then(|previous| answer.results.diff(&previous)) {
+            Some(changes) => {
+                answer.results = changes;
+                if answer.results.rows.is_empty() && !args.quiet {
+                    eprintln!("No changes since the last run.");
+                }
+            }
+            None if !args.quiet => {
+                eprintln!("note: no previous results for this question, so showing them all")
             }
+            None => {}
+        }
+    }
 
-            header_row.push(column.name().to_string());
+    if args.count {
+        let count = answer
+            .results
+            .columns
+            .iter()
+            .position(|column| column == "count")
+            .and_then(|i| answer.results.rows.first()?.get(i)?.as_integer())
+            .ok_or_else(|| eyre::eyre!("Counting the query's rows didn't return a number"))?;
+        invocation.row_count = Some(count as usize);
+        match render_options.format {
+            Format::Json | Format::JsonPretty => {
+                println!("{}", serde_json::json!({ "count": count }))
+            }
+            _ => println!("{count}"),
+        }
+        check_assertions(&args, count as usize)?;
+        return Ok(if count == 0 { error::EXIT_NO_ROWS } else { 0 });
+    }
 
-            let string_val = row
-                .try_get::<String, _>(column.name())
-                .or_else(|_| row.try_get::<i64, _>(column.name()).map(|x| x.to_string()))?;
+    if args.crns_only {
+        let Some(mut crns) = answer.results.crns() else {
+            eyre::bail!(
+                "The query didn't select a CRN column (it has: {}). Try asking for CRNs in the question.",
+                answer.results.columns.join(", ")
+            );
+        };
+        let omitted = crns.len().saturating_sub(args.limit);
+        if args.limit > 0 {
+            crns.truncate(args.limit);
+        }
+        if crns.is_empty() {
+            return Ok(error::EXIT_NO_ROWS);
+        }
+        println!(
+            "{}",
+            crns.join(args.crn_separator.as_deref().unwrap_or("\n"))
+        );
+        // On stderr, so what's pasted from stdout is only CRNs.
+        if args.limit > 0 && omitted > 0 && !args.quiet {
+            let crns = if omitted == 1 { "CRN" } else { "CRNs" };
+            eprintln!("… and {omitted} more {crns} (use --limit 0 for all)");
+        }
+        return Ok(0);
+    }
 
-            tab_row.push(string_val);
+    // Print the results.
+    let rendered = match &row_template {
+        Some(template) => {
+            template.check_columns(&answer.results.columns)?;
+            let mut results = answer.results.clone();
+            if render_options.limit > 0 {
+                results.rows.truncate(render_options.limit);
+            }
+            template.render(&results, &render_options)?
         }
+        None => render::render(&answer.results, question, &answer.sql, &render_options)?,
+    };
+    // A template with no rows (and no header or footer) renders nothing, not even a blank line.
+    if !rendered.is_empty() {
+        pager::print(&rendered, args.paging());
+    }
+    let omitted = answer
+        .results
+        .rows
+        .len()
+        .saturating_sub(render_options.limit);
+    let has_footer =
+        row_template.is_none() && !matches!(render_options.format, Format::Csv | Format::Tsv);
+    if render_options.limit > 0 && omitted > 0 && !has_footer && !args.quiet {
+        eprintln!("{}", render::more_rows_footer(omitted).trim_start());
+    }
 
-        let mut row = term_table::row::Row::new(tab_row);
-        row.has_separator = false;
-        table.rows.push(row);
+    if let Some(path) = &args.output {
+        let file_options = RenderOptions {
+            format: args.output_format.or(args.format).unwrap_or_default(),
+            max_width: None,
+            color: None,
+            highlight_terms: vec![],
+            hyperlink_url: None,
+            table_style: render::effective_table_style(args.table_style, args.ascii, false),
+            ..args.render_options()
+        };
+        let rendered = render::render(&answer.results, question, &answer.sql, &file_options)?;
+        output::write_file(path, &rendered)?;
     }
 
-    // insert the header row first
-    table.rows.insert(0, term_table::row::Row::new(header_row));
+    // Partial results can't be trusted for the checks below.
+    if answer.interrupted {
+        eprintln!("(interrupted after {} rows)", answer.results.rows.len());
+        return Ok(error::EXIT_INTERRUPTED);
+    }
+
+    check_assertions(&args, answer.results.rows.len())?;
 
-    // Second row has a separator (if we have a second row)
-    if table.rows.len() > 1 {
-        table.rows[1].has_separator = true;
+    // Let scripts tell "nothing matched" apart from success and failure.
+    if answer.results.rows.is_empty() {
+        return Ok(error::EXIT_NO_ROWS);
     }
 
-    // Print the table.
-    println!("{}", table.render());
+    Ok(0)
+}
 
+/// Sanity checks for scripts: `--assert-nonempty` and `--assert-max-rows`. These fail with the
+/// generic exit code 1.
+fn check_assertions(args: &Args, row_count: usize) -> Result<()> {
+    if args.assert_nonempty && row_count == 0 {
+        eyre::bail!("The query returned no rows (--assert-nonempty)");
+    }
+    if let Some(max_rows) = args.assert_max_rows {
+        if row_count > max_rows {
+            eyre::bail!(
+                "The query returned {row_count} rows, more than --assert-max-rows {max_rows}"
+            );
+        }
+    }
     Ok(())
 }
 
-const DB_INFO_PROMPT: &str = r#"
-
--- Course sections
-CREATE TABLE sections (
-	id text not null primary key,
-	term text not null, -- Course term, like 202402 for Spring 2024
-	term_description text not null, -- Human-readable term description, like 'Spring 2024'
-	crn text not null, -- Course Registration Number. Unique to each section, and necessary to register.
-	number text not null, -- Course number, like '9000' in 'PHYS 9000'
-	subject text not null, -- Course subject, like 'PHYS' in 'PHYS 9000'
-	subject_description text not null, -- Human-readable subject description, like 'Physics'
-
-	section text not null, -- Section number, like 'A' or 'O'. Note that an 'O' section actually indicates an online course, even if the campus is 'Georgia Tech-Atlanta *'.
-
-	campus text not null, -- What campus. Note that in-person students can't register for 'Online' courses. One of:
-    --  'Georgia Tech-Atlanta *'
-    --  'GT Lorraine-Undergrad Programs'
-    --  'Foreign Exchange'
-    --  'GT Lorraine-Graduate Programs'
-    --  'Georgia Tech - Shenzhen'
-    --  'Video'
-    --  'MBA Evening Program'
-    --  'Online'
-    --  'Georgia Tech Studies Abroad'
-    --  'Graduate Certificate'
-    --  'Georgia Southern/GTREP'
-    --  'GT, Peking University, &amp; Emory'
-    --  'Georgia Tech-Savannah'
-    --  'Georgia Tech - Korea'
-    --  'Global'
-
-	schedule_type text not null, -- One of:
-    --  'Lecture*'
-    --  'Dissertation*'
-    --  'Directed Study*'
-    --  'Thesis*'
-    --  'Seminar*'
-    --  'Supervised Laboratory*'
-    --  'Studio*'
-    --  'Internship/Practicum*'
-    --  'Unsupervised Laboratory*'
-    --  'Breakout*'
-    --  'Mixed Laboratory*'
-    --  'Recitation*'
-    --  'Co-op Work Assignment'
-    --  'Practice Teaching*'
-    --  'Common Exam*'
-    
-	course_title not null, -- The title of the course.
-	credit_hours integer not null, -- The number of credit hours awarded for taking this section.
-	
-    -- Information about who is enrolled in the class:
-    max_enrollment integer not null,
-	enrollment integer not null,
-	seats_available integer not null,
-	waitlist_capacity integer not null,
-	waitlist_count integer not null,
-	waitlist_available integer not null,
-
-	open string not null, -- Whether the section is open for registration. 'true' or 'false'.
-	attributes text, -- Comma-separated list of attributes, like 'ETHS,HUM' for a course that fulfills the ethics and humanities requirement.
-	raw json not null -- Raw payload from registration system (can be ignored)
-);
-
--- Names and contact informaiton of faculty.
-CREATE TABLE faculty (
-	id text not null primary key,
-	name text not null,
-	email text not null
-);
-
--- The faculty teaching each section.
-CREATE TABLE course_faculty (
-	course_id text not null references sections(id),
-	faculty_id text not null references faculty(id),
-	primary key (course_id, faculty_id)
-);
-
-"#;
+/// Mention, once per user, that piped output is TSV rather than a table.
+fn note_auto_format() {
+    let state_dir = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")));
+    let Some(marker) = state_dir.map(|dir| dir.join("reg-agent").join("auto-format-noted")) else {
+        return;
+    };
+    if marker.exists() {
+        return;
+    }
+
+    eprintln!("note: stdout isn't a terminal, so results are printed as TSV; pass --format table to override.");
+
+    // Best effort: if the marker can't be written, the note just shows up again next time.
+    if let Some(parent) = marker.parent() {
+        let _ = std::fs::create_dir_all(parent).and_then(|()| std::fs::write(&marker, ""));
+    }
+}
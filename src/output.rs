@@ -0,0 +1,37 @@
+use eyre::{Context, Result};
+use std::path::Path;
+
+/// Make sure results can be written to `path`: it must not exist unless `force` is set, and its
+/// parent directory must exist (or be created, with `mkdirs`).
+pub fn check_path(path: &Path, force: bool, mkdirs: bool) -> Result<()> {
+    if path.exists() && !force {
+        eyre::bail!(
+            "{} already exists; pass --force to overwrite it",
+            path.display()
+        );
+    }
+
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty());
+    if let Some(parent) = parent {
+        if !parent.exists() {
+            if !mkdirs {
+                eyre::bail!(
+                    "Directory {} doesn't exist; pass --mkdirs to create it",
+                    parent.display()
+                );
+            }
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("Failed to create {}", parent.display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write rendered results to `path`, with a trailing newline like on the terminal.
+pub fn write_file(path: &Path, rendered: &str) -> Result<()> {
+    std::fs::write(path, format!("{rendered}\n"))
+        .wrap_err_with(|| format!("Failed to write {}", path.display()))
+}
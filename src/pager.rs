@@ -0,0 +1,69 @@
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+use terminal_size::Height;
+
+use crate::interrupt::{self, OnCtrlC};
+
+/// When to send output through the pager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Paging {
+    /// Only when the output is taller than the terminal.
+    Auto,
+    Always,
+    Never,
+}
+
+/// Print `text` to stdout, through `$PAGER` (or `less -FRX`) if stdout is a terminal and `paging`
+/// calls for it. Falls back to printing directly if the pager can't be started.
+pub fn print(text: &str, paging: Paging) {
+    if std::io::stdout().is_terminal() && wants_pager(text, paging) && page(text).is_ok() {
+        return;
+    }
+    println!("{text}");
+}
+
+fn wants_pager(text: &str, paging: Paging) -> bool {
+    match paging {
+        Paging::Always => true,
+        Paging::Never => false,
+        Paging::Auto => match terminal_size::terminal_size() {
+            // Leave a line for the shell prompt. Some ptys report a height of zero; don't page there.
+            Some((_, Height(height))) if height > 0 => text.lines().count() >= height as usize,
+            _ => false,
+        },
+    }
+}
+
+/// Run the pager with `text` on its stdin, and wait for the user to quit it.
+fn page(text: &str) -> std::io::Result<()> {
+    // Like git: quit if it fits on one screen, pass colors through, and don't clear the screen.
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| "less -FRX".to_string());
+    let mut words = pager.split_whitespace();
+    let program = words.next().expect("pager is not empty");
+
+    let mut child = Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    // The pager gets Ctrl-C too. Like git, leave it to the pager rather than exiting under it.
+    interrupt::set(OnCtrlC::Ignore);
+
+    // The pager closes its stdin if the user quits before reading everything; that's fine.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    if let Err(err) = writeln!(stdin, "{text}") {
+        if err.kind() != std::io::ErrorKind::BrokenPipe {
+            interrupt::set(OnCtrlC::Exit);
+            return Err(err);
+        }
+    }
+    drop(stdin);
+
+    let status = child.wait();
+    interrupt::set(OnCtrlC::Exit);
+    status?;
+    Ok(())
+}
@@ -0,0 +1,361 @@
+use async_openai::types::Role;
+use eyre::{Context, Result};
+use indoc::formatdoc;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::path::Path;
+use tiktoken_rs::CoreBPE;
+
+/// Knobs that change the instructions given to the model.
+pub struct PromptOptions {
+    /// Whether the student is interested in online sections.
+    pub include_online: bool,
+
+    /// Example rows from `sections`, as CSV, to show the model what values look like.
+    pub sample_rows: Option<String>,
+
+    /// Other course databases attached to the connection, as (schema name, file) pairs.
+    pub attached: Vec<(String, String)>,
+
+    /// Ask for the query as a JSON object (`{"sql": "..."}`) rather than bare text.
+    pub json_response: bool,
+
+    /// Let the model ask a clarifying question (starting with `?`) instead of guessing.
+    pub clarify: bool,
+
+    /// Tell the model it may modify the database when asked to.
+    pub allow_writes: bool,
+
+    /// Replacement for the first system message, with `{{schema}}` standing for the schema.
+    pub system_prompt: Option<String>,
+
+    /// Extra instructions, each sent as a system message before the question.
+    pub append_system: Vec<String>,
+
+    /// Names of course attribute codes, as (code, name) pairs, so the model can find sections
+    /// by requirement.
+    pub attribute_names: Vec<(String, String)>,
+}
+
+/// Build the messages sent to the model to answer `question`, given the database `schema`.
+pub fn build_prompt(schema: &str, question: &str, options: &PromptOptions) -> Vec<(Role, String)> {
+    let mut prompt: Vec<(Role, String)> = vec![];
+
+    let campus_guidance = if options.include_online {
+        "Assume this student is enrolled in the Atlanta campus. They are interested in both in-person and online sections."
+    } else {
+        "Assume this student is enrolled in the Atlanta campus, and only interested in courses they can register for in-person. Always exclude online sections: those with campus 'Online', AND those whose section starts with 'O'. An 'O' section is online even when its campus is 'Georgia Tech-Atlanta *'."
+    };
+
+    let sample_rows = match &options.sample_rows {
+        Some(csv) => formatdoc! {r#"
+            Here are some example rows from the `sections` table, as CSV. Pay attention to the format of each value:
+            ```csv
+            {csv}
+            ```
+        "#},
+        None => String::new(),
+    };
+
+    let attached = if options.attached.is_empty() {
+        String::new()
+    } else {
+        let list: String = options
+            .attached
+            .iter()
+            .map(|(name, file)| format!("- `{name}` (from `{file}`)\n"))
+            .collect();
+        formatdoc! {r#"
+            Besides the main database, these databases are attached. Each has the same schema, usually with sections from a different term:
+            {list}
+            Refer to their tables by database name, like `{example}.sections`. If the question could span databases, combine them with UNION ALL.
+        "#, example = options.attached[0].0}
+    };
+
+    let attribute_names = if options.attribute_names.is_empty() {
+        String::new()
+    } else {
+        let list: Vec<String> = options
+            .attribute_names
+            .iter()
+            .map(|(code, name)| format!("- `{code}`: {name}"))
+            .collect();
+        let list = list.join("\n");
+        formatdoc! {r#"
+            These are the codes used in `attributes`, and the requirements they stand for. Use the code when a student asks about a requirement:
+            {list}
+        "#}
+    };
+
+    let instructions = match &options.system_prompt {
+        Some(template) => template.replace("{{schema}}", schema),
+        None => formatdoc! {r#"
+            You are an agent designed to help students with course registration at Georgia Tech. You have access to a SQLite database of available sections to register. Your job is to write a query against that database to answer a student's question about course registration. You should be very selective about the columns you select from the database---only include important information to answer the question. Always include a CRN, if it makes sense to do so. Do NOT include enrollment information if the user doesn't ask for it.
+
+            {campus_guidance}
+
+            If a student refers to a course like 'CS 1331', they are referring to the course number, '1331' and subject 'CS'. If a student refers to 'CS 8803 ANI', they're refering to the 'ANI' section of CS 8803.
+
+            Here is the schema of the database:
+            ```sql
+            {schema}
+            ```
+            {attribute_names}{attached}{sample_rows}
+            The next message will have a question from a student. Read it carefully:
+        "#},
+    };
+    prompt.push((Role::System, instructions));
+    for text in &options.append_system {
+        prompt.push((Role::System, text.clone()));
+    }
+
+    prompt.push((Role::User, question.to_string()));
+
+    // The pinned async-openai doesn't expose `response_format`, so JSON output is requested here.
+    let response_instructions = if options.json_response {
+        r#"Respond ONLY with a JSON object of the form {"sql": "<the query>"}, or else it won't work and the student will be very sad."#
+    } else {
+        "Respond ONLY with the text of the SQL query, or else it won't work and the student will be very sad."
+    };
+
+    let clarify_instructions = if options.clarify {
+        " If the question is too ambiguous to answer confidently (for example, it doesn't say which term it's about and that matters), respond instead with a single short clarifying question for the student, starting with `?`."
+    } else {
+        ""
+    };
+
+    let write_instructions = if options.allow_writes {
+        " If the student asks to change the database, you may write INSERT, UPDATE, or DELETE statements instead."
+    } else {
+        ""
+    };
+
+    prompt.push((
+        Role::System,
+        format!("Given the following question, write a single SQL query to answer it. Take a deep breath and think carefully before responding. {response_instructions}{clarify_instructions}{write_instructions}"),
+    ));
+
+    prompt
+}
+
+/// Read a replacement for the first system message, for `--system-prompt-file`. Warns if it
+/// has no `{{schema}}` placeholder, since the model won't see the schema then.
+pub fn load_system_prompt(path: &Path) -> Result<String> {
+    let template = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read system prompt {}", path.display()))?;
+    if !template.contains("{{schema}}") {
+        eprintln!(
+            "warning: {} has no {{{{schema}}}} placeholder, so the model won't see the schema",
+            path.display()
+        );
+    }
+    Ok(template)
+}
+
+/// Read a replacement for `DB_INFO_PROMPT` from the `schema_sql` key of a TOML or YAML file, so
+/// the tool can be pointed at a differently structured database.
+pub fn load_schema_override(path: &Path) -> Result<String> {
+    #[derive(serde::Deserialize)]
+    struct SchemaOverride {
+        schema_sql: String,
+    }
+
+    let schema: SchemaOverride = read_toml_or_yaml(path, "Schema override")?;
+    Ok(schema.schema_sql)
+}
+
+/// Read names for course attribute codes from a TOML or YAML file of `CODE = "Name"` pairs, for
+/// `--attributes-file`. They're added to the built-in names, replacing any for the same code.
+pub fn load_attribute_names(path: &Path) -> Result<Vec<(String, String)>> {
+    let names: BTreeMap<String, String> = read_toml_or_yaml(path, "Attributes file")?;
+    Ok(names.into_iter().collect())
+}
+
+/// Parse a TOML or YAML file, depending on its extension. `what` names the file in errors.
+fn read_toml_or_yaml<T: DeserializeOwned>(path: &Path, what: &str) -> Result<T> {
+    let text = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read {} {}", what.to_lowercase(), path.display()))?;
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    match extension {
+        "toml" => {
+            toml::from_str(&text).wrap_err_with(|| format!("Invalid TOML in {}", path.display()))
+        }
+        "yaml" | "yml" => serde_yaml::from_str(&text)
+            .wrap_err_with(|| format!("Invalid YAML in {}", path.display())),
+        _ => eyre::bail!(
+            "{what} {} must be a .toml, .yaml, or .yml file",
+            path.display()
+        ),
+    }
+}
+
+/// A message as saved by `--dump-prompt`.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Message {
+    role: Role,
+    content: String,
+}
+
+/// Save the messages of a prompt to `path` as a JSON array of `{"role", "content"}` objects.
+pub fn save(path: &Path, prompt: &[(Role, String)]) -> Result<()> {
+    let messages: Vec<Message> = prompt
+        .iter()
+        .map(|(role, content)| Message {
+            role: role.clone(),
+            content: content.clone(),
+        })
+        .collect();
+    let json = serde_json::to_string_pretty(&messages)?;
+    std::fs::write(path, json + "\n")
+        .wrap_err_with(|| format!("Failed to write prompt to {}", path.display()))
+}
+
+/// Load a prompt saved by `save`.
+pub fn load(path: &Path) -> Result<Vec<(Role, String)>> {
+    let json = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read prompt from {}", path.display()))?;
+    let messages: Vec<Message> = serde_json::from_str(&json)
+        .wrap_err_with(|| format!("{} is not a saved prompt", path.display()))?;
+    Ok(messages
+        .into_iter()
+        .map(|message| (message.role, message.content))
+        .collect())
+}
+
+/// The tokenizer for `model`, or GPT-4's for models tiktoken doesn't know. It's slow to build, so
+/// build it once and pass it to `count_tokens`.
+pub fn tokenizer(model: &str) -> CoreBPE {
+    tiktoken_rs::get_bpe_from_model(model)
+        .or_else(|_| tiktoken_rs::cl100k_base())
+        .expect("the built-in tokenizer loads")
+}
+
+/// Count the tokens in a prompt with a `tokenizer`. Each message also costs a few tokens of
+/// framing.
+pub fn count_tokens(prompt: &[(Role, String)], bpe: &CoreBPE) -> usize {
+    const TOKENS_PER_MESSAGE: usize = 4;
+
+    prompt
+        .iter()
+        .map(|(_, content)| bpe.encode_ordinary(content).len() + TOKENS_PER_MESSAGE)
+        .sum()
+}
+
+/// Context windows of OpenAI models, in tokens, by model name prefix. The first match wins.
+const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    ("gpt-4o", 128000),
+    ("gpt-4-turbo", 128000),
+    ("gpt-4-0125", 128000),
+    ("gpt-4-1106", 128000),
+    ("gpt-4-32k", 32768),
+    ("gpt-4", 8192),
+    ("gpt-3.5-turbo", 16385),
+];
+
+/// The context window of `model`, in tokens, or `None` if it isn't a model we know.
+pub fn context_window(model: &str) -> Option<usize> {
+    CONTEXT_WINDOWS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|&(_, tokens)| tokens)
+}
+
+/// Cut roughly `overflow_tokens` tokens off the end of the schema, on a line boundary.
+pub fn truncate_schema(schema: &str, overflow_tokens: usize) -> String {
+    let keep_chars = schema.chars().count().saturating_sub(overflow_tokens * 4);
+    let mut truncated: String = schema.chars().take(keep_chars).collect();
+    if let Some(last_newline) = truncated.rfind('\n') {
+        truncated.truncate(last_newline);
+    }
+    truncated
+}
+
+pub const DB_INFO_PROMPT: &str = r#"
+
+-- Course sections
+CREATE TABLE sections (
+	id text not null primary key,
+	term text not null, -- Course term, like 202402 for Spring 2024
+	term_description text not null, -- Human-readable term description, like 'Spring 2024'
+	crn text not null, -- Course Registration Number. Unique to each section, and necessary to register.
+	number text not null, -- Course number, like '9000' in 'PHYS 9000'
+	subject text not null, -- Course subject, like 'PHYS' in 'PHYS 9000'
+	subject_description text not null, -- Human-readable subject description, like 'Physics'
+
+	section text not null, -- Section number, like 'A' or 'O'. Note that an 'O' section actually indicates an online course, even if the campus is 'Georgia Tech-Atlanta *'.
+
+	campus text not null, -- What campus. Note that in-person students can't register for 'Online' courses. One of:
+    --  'Georgia Tech-Atlanta *'
+    --  'GT Lorraine-Undergrad Programs'
+    --  'Foreign Exchange'
+    --  'GT Lorraine-Graduate Programs'
+    --  'Georgia Tech - Shenzhen'
+    --  'Video'
+    --  'MBA Evening Program'
+    --  'Online'
+    --  'Georgia Tech Studies Abroad'
+    --  'Graduate Certificate'
+    --  'Georgia Southern/GTREP'
+    --  'GT, Peking University, &amp; Emory'
+    --  'Georgia Tech-Savannah'
+    --  'Georgia Tech - Korea'
+    --  'Global'
+
+	schedule_type text not null, -- One of:
+    --  'Lecture*'
+    --  'Dissertation*'
+    --  'Directed Study*'
+    --  'Thesis*'
+    --  'Seminar*'
+    --  'Supervised Laboratory*'
+    --  'Studio*'
+    --  'Internship/Practicum*'
+    --  'Unsupervised Laboratory*'
+    --  'Breakout*'
+    --  'Mixed Laboratory*'
+    --  'Recitation*'
+    --  'Co-op Work Assignment'
+    --  'Practice Teaching*'
+    --  'Common Exam*'
+    
+	course_title not null, -- The title of the course.
+	credit_hours integer not null, -- The number of credit hours awarded for taking this section.
+	
+    -- Information about who is enrolled in the class:
+    max_enrollment integer not null,
+	enrollment integer not null,
+	seats_available integer not null,
+	waitlist_capacity integer not null,
+	waitlist_count integer not null,
+	waitlist_available integer not null,
+
+	open string not null, -- Whether the section is open for registration. 'true' or 'false'.
+	attributes text, -- Comma-separated list of attributes, like 'ETHS,HUM' for a course that fulfills the ethics and humanities requirement.
+	raw json not null -- Raw payload from registration system (can be ignored)
+);
+
+-- Names and contact informaiton of faculty. To find who teaches a section, join through `course_faculty`.
+CREATE TABLE faculty (
+	id text not null primary key,
+	name text not null, -- Instructor's full name, like 'Thad Starner'. Use this when the student asks who teaches something.
+	email text not null -- Instructor's email address
+);
+
+-- The faculty teaching each section. A section can have several instructors, or none.
+CREATE TABLE course_faculty (
+	course_id text not null references sections(id), -- `sections.id`, NOT the CRN
+	faculty_id text not null references faculty(id),
+	primary key (course_id, faculty_id)
+);
+
+-- Example: who teaches each section of CS 1331?
+--   SELECT s.crn, s.section, f.name AS instructor
+--   FROM sections s
+--   LEFT JOIN course_faculty cf ON cf.course_id = s.id
+--   LEFT JOIN faculty f ON f.id = cf.faculty_id
+--   WHERE s.subject = 'CS' AND s.number = '1331';
+
+"#;
@@ -0,0 +1,2644 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
+use eyre::{Context, Result};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, TypeInfo, ValueRef};
+use std::fmt;
+use std::io::IsTerminal;
+use term_table::table_cell::{Alignment, TableCell};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// How to print query results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// A table, with borders set by `--table-style`.
+    #[default]
+    Table,
+    /// A JSON array of objects, keyed by column name.
+    Json,
+    /// Like `json`, but indented.
+    JsonPretty,
+    /// Comma-separated values, with a header row.
+    Csv,
+    /// Tab-separated values, with a header row and no quoting.
+    Tsv,
+    /// A GitHub-flavored markdown table.
+    Markdown,
+    /// An HTML `<table>`.
+    Html,
+}
+
+impl Format {
+    /// Whether this format is meant for people to read, rather than other programs.
+    fn is_for_display(self) -> bool {
+        matches!(self, Format::Table | Format::Markdown | Format::Html)
+    }
+}
+
+/// Where to collapse enrollment columns into a single `seats` column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CompactEnrollment {
+    /// Tables, markdown, and HTML.
+    Display,
+    /// Every format, including JSON and CSV.
+    All,
+}
+
+/// Colors for sections in table output, by availability.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorScheme {
+    /// Green for open seats, yellow for waitlist spots, and red for closed sections.
+    #[default]
+    Default,
+    /// Blue for open seats, yellow for waitlist spots, and dim for closed sections.
+    Colorblind,
+}
+
+impl ColorScheme {
+    /// ANSI SGR codes for sections with open seats, with waitlist spots, and closed sections.
+    fn codes(self) -> [&'static str; 3] {
+        match self {
+            ColorScheme::Default => ["32", "33", "2;31"],
+            ColorScheme::Colorblind => ["34", "33", "2"],
+        }
+    }
+}
+
+/// Whether to write ANSI colors to `stream`: only if it's a terminal, `NO_COLOR` isn't set
+/// (https://no-color.org), and `TERM` isn't `dumb`.
+pub fn color_enabled(stream: &impl IsTerminal) -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let dumb_term = std::env::var_os("TERM").is_some_and(|term| term == "dumb");
+    !no_color && !dumb_term && stream.is_terminal()
+}
+
+/// When to link CRNs in tables to their sections' pages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Hyperlinks {
+    On,
+    Off,
+    /// When stdout is a terminal known to support them.
+    #[default]
+    Auto,
+}
+
+/// Default for `--hyperlink-url`: the section's page in OSCAR.
+pub const DEFAULT_HYPERLINK_URL: &str =
+    "https://oscar.gatech.edu/bprod/bwckschd.p_disp_detail_sched?term_in={term}&crn_in={crn}";
+
+/// Whether `stream` is a terminal that supports OSC 8 hyperlinks. Terminals can't be asked, so
+/// this goes by the environment variables the ones that do set.
+pub fn hyperlinks_supported(stream: &impl IsTerminal) -> bool {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+    if !stream.is_terminal() || var("TERM") == "dumb" {
+        return false;
+    }
+
+    matches!(
+        var("TERM_PROGRAM").as_str(),
+        "iTerm.app" | "WezTerm" | "vscode" | "Hyper" | "ghostty"
+    ) || var("TERM").contains("kitty")
+        || var("TERM").starts_with("foot")
+        || !var("WT_SESSION").is_empty()
+        || !var("KONSOLE_VERSION").is_empty()
+        || var("VTE_VERSION")
+            .parse::<u32>()
+            .is_ok_and(|version| version >= 5000)
+}
+
+/// When to print table output as one block per record, instead of a table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Expanded {
+    #[default]
+    Never,
+    Always,
+    /// When the table would be wider than the maximum width.
+    Auto,
+}
+
+/// Borders for table output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum TableStyle {
+    /// Box-drawing lines with rounded corners.
+    #[default]
+    Rounded,
+    /// `+`, `-`, and `|`, which survive plain-text email and old terminals.
+    Ascii,
+    /// Columns two spaces apart, with a dashed line under the header.
+    Plain,
+    /// Columns two spaces apart, with no lines at all.
+    Borderless,
+}
+
+/// How boolean columns are shown in tables.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum BoolStyle {
+    /// ✓ and ✗, or Y and N with `--ascii`.
+    #[default]
+    Symbols,
+    /// Yes and No.
+    Words,
+}
+
+/// Pick the table style. An explicit `--table-style` always wins; otherwise terminals get rounded
+/// borders, and anything else (or `--ascii`) gets ASCII ones.
+pub fn effective_table_style(
+    explicit: Option<TableStyle>,
+    ascii: bool,
+    is_tty: bool,
+) -> TableStyle {
+    match explicit {
+        Some(style) => style,
+        None if is_tty && !ascii => TableStyle::Rounded,
+        None => TableStyle::Ascii,
+    }
+}
+
+/// Pick the output format for stdout. An explicit `--format` always wins; otherwise, like `ls`,
+/// people at a terminal get a table and pipes get TSV, which is easy to `grep` and `cut`.
+pub fn effective_format(explicit: Option<Format>, stdout_is_tty: bool) -> Format {
+    match explicit {
+        Some(format) => format,
+        None if stdout_is_tty => Format::Table,
+        None => Format::Tsv,
+    }
+}
+
+#[derive(Default)]
+pub struct RenderOptions {
+    pub format: Format,
+
+    /// Wrap JSON output in an object with the SQL and row count.
+    pub json_envelope: bool,
+
+    /// Leave out the header row.
+    pub no_header: bool,
+
+    /// Show only this many rows, with a footer counting the rest. 0 shows every row.
+    pub limit: usize,
+
+    /// Field separator for TSV output. Defaults to a tab.
+    pub field_separator: Option<char>,
+
+    /// Word-wrap table cells to this many characters. 0 disables wrapping.
+    pub wrap_width: usize,
+
+    /// Truncate table and markdown cells longer than this, with an ellipsis. 0 disables.
+    pub max_col_width: usize,
+
+    /// Shrink the widest text columns until the table fits in this many columns.
+    pub max_width: Option<usize>,
+
+    /// Word-wrap columns shrunk to fit `max_width`, rather than truncating them.
+    pub wrap_to_fit: bool,
+
+    /// Print each row as a block of `column | value` lines.
+    pub expanded: Expanded,
+
+    /// Borders for tables.
+    pub table_style: TableStyle,
+
+    /// Color table rows by seat availability. `None` disables color.
+    pub color: Option<ColorScheme>,
+
+    /// Highlight these words (lowercase) wherever they appear in table cells, ignoring case.
+    pub highlight_terms: Vec<String>,
+
+    /// Collapse enrollment columns into a single `seats` column.
+    pub compact_enrollment: Option<CompactEnrollment>,
+
+    /// Group rows with the same values in these columns under a heading, leaving the columns out
+    /// of the rows. Rows must already be sorted by them. Only for tables and JSON.
+    pub group_by: Vec<String>,
+
+    /// Columns holding booleans (like `open`, which is 'true' or 'false'). They're shown as ✓/✗
+    /// in tables and as JSON booleans.
+    pub bool_columns: Vec<String>,
+
+    /// How `bool_columns` are shown in tables.
+    pub bool_style: BoolStyle,
+
+    /// Show booleans in tables as Y/N instead of ✓/✗.
+    pub ascii: bool,
+
+    /// Show attribute codes (like `ETHS,HUM`) as names in tables, markdown, and HTML.
+    pub expand_attributes: bool,
+
+    /// Names of attribute codes for `expand_attributes`, as (code, name) pairs.
+    pub attribute_names: Vec<(String, String)>,
+
+    /// Link CRNs in tables to this URL, with `{term}` and `{crn}` filled in. `None` disables.
+    pub hyperlink_url: Option<String>,
+
+    /// Show the `raw` column as JSON, eliding objects and arrays nested deeper than this in
+    /// cells (0 elides nothing). `None` shows it as stored.
+    pub raw_depth: Option<usize>,
+
+    /// Show every value exactly as stored, ignoring `bool_columns`.
+    pub raw_values: bool,
+
+    /// Include the question and SQL in HTML output.
+    pub html_details: bool,
+
+    /// Embed a default stylesheet in HTML output.
+    pub html_style: bool,
+
+    /// Write a complete HTML document, rather than just the table.
+    pub html_full_page: bool,
+}
+
+/// A single cell, decoded according to its SQLite storage class.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+
+    /// Shown as base64.
+    Blob(Vec<u8>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Integer(x) => write!(f, "{x}"),
+            Value::Real(x) => write!(f, "{x}"),
+            Value::Text(x) => f.write_str(x),
+            Value::Blob(x) => f.write_str(&BASE64_STANDARD.encode(x)),
+        }
+    }
+}
+
+impl Value {
+    /// The value as a boolean, if it looks like one: 'true'/'false', or 1/0.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Text(text) if text.eq_ignore_ascii_case("true") => Some(true),
+            Value::Text(text) if text.eq_ignore_ascii_case("false") => Some(false),
+            Value::Integer(1) => Some(true),
+            Value::Integer(0) => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Null => serializer.serialize_none(),
+            Value::Integer(x) => serializer.serialize_i64(*x),
+            Value::Real(x) => serializer.serialize_f64(*x),
+            Value::Text(x) => serializer.serialize_str(x),
+            // Like MongoDB's extended JSON, so bytes can be told apart from text.
+            Value::Blob(x) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("$binary", &BASE64_STANDARD.encode(x))?;
+                map.end()
+            }
+        }
+    }
+}
+
+/// Query results, decoded into plain values for rendering.
+#[derive(Clone)]
+pub struct ResultSet {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl ResultSet {
+    /// Decode `rows` of a query with these `columns`, which come from the statement rather than
+    /// the rows so that they're known even when there are no rows.
+    pub fn from_rows(columns: Vec<String>, rows: &[SqliteRow]) -> Result<Self> {
+        let rows = rows
+            .iter()
+            .map(|row| {
+                columns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| decode(row, i, name))
+                    .collect::<Result<_>>()
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(ResultSet { columns, rows })
+    }
+
+    /// Drop rows for online sections: those on the 'Online' campus, and 'O' sections (which are
+    /// online even on the Atlanta campus). Only works if the query selected `campus` or
+    /// `section`. Returns the number of rows dropped.
+    pub fn exclude_online(&mut self) -> usize {
+        let campus = self.column_index("campus");
+        let section = self.column_index("section");
+
+        let is_online = |row: &[Value]| {
+            let campus_online =
+                campus.is_some_and(|i| matches!(&row[i], Value::Text(c) if c == "Online"));
+            let section_online =
+                section.is_some_and(|i| matches!(&row[i], Value::Text(s) if s.starts_with('O')));
+            campus_online || section_online
+        };
+
+        let before = self.rows.len();
+        self.rows.retain(|row| !is_online(row));
+        before - self.rows.len()
+    }
+
+    /// Keep only the named columns, in the given order. Names match case-insensitively. Returns
+    /// the names that weren't found.
+    pub fn select_columns(&mut self, names: &[String]) -> Vec<String> {
+        let (found, missing): (Vec<_>, Vec<_>) = names
+            .iter()
+            .map(|name| {
+                let index = self.column_index(name);
+                (name, index)
+            })
+            .partition(|(_, index)| index.is_some());
+        let indices: Vec<usize> = found.iter().filter_map(|(_, index)| *index).collect();
+
+        self.columns = indices.iter().map(|&i| self.columns[i].clone()).collect();
+        for row in &mut self.rows {
+            *row = indices.iter().map(|&i| row[i].clone()).collect();
+        }
+
+        missing.into_iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Drop the named columns, if present. Names match case-insensitively. Returns the names that
+    /// weren't found.
+    pub fn hide_columns(&mut self, names: &[String]) -> Vec<String> {
+        let is_named = |column: &String| names.iter().any(|n| n.eq_ignore_ascii_case(column));
+        let missing = names
+            .iter()
+            .filter(|name| !self.columns.iter().any(|c| c.eq_ignore_ascii_case(name)))
+            .cloned()
+            .collect();
+        let keep: Vec<String> = self
+            .columns
+            .iter()
+            .filter(|column| !is_named(column))
+            .cloned()
+            .collect();
+        self.select_columns(&keep);
+        missing
+    }
+
+    /// The index of the column named `name`, ignoring case.
+    fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|c| c.eq_ignore_ascii_case(name))
+    }
+
+    /// The color for a row, based on its `open`, `seats_available`, and `waitlist_available`
+    /// columns: closed, open with seats, or open with waitlist spots. `None` if it has none of
+    /// them. Rows of a `diff` are colored by their change instead, in the same colors.
+    fn availability_color(&self, row: &[Value], scheme: ColorScheme) -> Option<&'static str> {
+        let column = |name: &str| {
+            let i = self.column_index(name)?;
+            Some(&row[i])
+        };
+        let [seats, waitlist, closed] = scheme.codes();
+
+        if let Some(Value::Text(change)) = column(CHANGE_COLUMN) {
+            match change.as_str() {
+                "+" => return Some(seats),
+                "~" => return Some(waitlist),
+                "-" => return Some(closed),
+                _ => {}
+            }
+        }
+        let has_spots = |name: &str| match column(name) {
+            Some(Value::Integer(n)) => Some(*n > 0),
+            Some(Value::Real(n)) => Some(*n > 0.0),
+            _ => None,
+        };
+
+        if matches!(column("open"), Some(Value::Text(open)) if open == "false") {
+            return Some(closed);
+        }
+        match (
+            has_spots("seats_available"),
+            has_spots("waitlist_available"),
+        ) {
+            (Some(true), _) => Some(seats),
+            (_, Some(true)) => Some(waitlist),
+            (Some(false), _) | (_, Some(false)) => Some(closed),
+            (None, None) => None,
+        }
+    }
+
+    /// Collapse rows with the same `crn` (like cross-listed sections) into the first one, with
+    /// every distinct subject joined by `/`. Returns the number of rows removed, or `None` if
+    /// there's no `crn` column.
+    pub fn dedup_crns(&mut self) -> Option<usize> {
+        let crn = self.column_index("crn")?;
+        let subject = self.column_index("subject");
+
+        let before = self.rows.len();
+        let mut kept: Vec<Vec<Value>> = vec![];
+        for row in std::mem::take(&mut self.rows) {
+            let Some(first) = kept.iter_mut().find(|kept| kept[crn] == row[crn]) else {
+                kept.push(row);
+                continue;
+            };
+
+            if let Some(subject) = subject {
+                let other = row[subject].to_string();
+                let subjects = first[subject].to_string();
+                if !other.is_empty() && !subjects.split('/').any(|s| s == other) {
+                    first[subject] = Value::Text(format!("{subjects}/{other}"));
+                }
+            }
+        }
+        self.rows = kept;
+
+        Some(before - self.rows.len())
+    }
+
+    /// Keep only the first row with each value of the named column (matched case-insensitively).
+    /// Returns the number of rows removed, or `None` if there's no such column.
+    pub fn distinct_by(&mut self, name: &str) -> Option<usize> {
+        let column = self.column_index(name)?;
+
+        let before = self.rows.len();
+        let mut seen: Vec<Value> = vec![];
+        self.rows.retain(|row| {
+            if seen.contains(&row[column]) {
+                return false;
+            }
+            seen.push(row[column].clone());
+            true
+        });
+        Some(before - self.rows.len())
+    }
+
+    /// Each row's (CRN, URL) pair for `--hyperlinks`, filling `{term}` and `{crn}` in the URL
+    /// template. `None` without both a `crn` and a `term` column.
+    fn section_links(&self, url: &str) -> Option<Vec<(String, String)>> {
+        let crn = self.column_index("crn")?;
+        let term = self.column_index("term")?;
+        let links = self
+            .rows
+            .iter()
+            .map(|row| {
+                let crn = row[crn].to_string();
+                let url = url
+                    .replace("{term}", &row[term].to_string())
+                    .replace("{crn}", &crn);
+                (crn, url)
+            })
+            .collect();
+        Some(links)
+    }
+
+    /// The rows that changed since `previous`, matched by CRN, under a leading `change` column:
+    /// `+` for new rows, `-` for rows that are gone, and `~` for rows with other values, which
+    /// are shown as `old → new`. Values are compared as text. `None` without a `crn` column in
+    /// both.
+    pub fn diff(&self, previous: &ResultSet) -> Option<ResultSet> {
+        let crn_column = self.column_index("crn")?;
+        let previous_crn_column = previous.column_index("crn")?;
+        // The previous rows' values, in this result set's columns.
+        let previous_columns: Vec<Option<usize>> = self
+            .columns
+            .iter()
+            .map(|column| previous.column_index(column))
+            .collect();
+        let previous_row = |row: &[Value]| -> Vec<Value> {
+            previous_columns
+                .iter()
+                .map(|&i| i.map_or(Value::Null, |i| row[i].clone()))
+                .collect()
+        };
+        let marked = |change: &str, row: Vec<Value>| {
+            std::iter::once(Value::Text(change.to_string()))
+                .chain(row)
+                .collect::<Vec<_>>()
+        };
+
+        // The first row with each CRN, as `find` would pick.
+        let mut previous_by_crn = std::collections::HashMap::new();
+        for old in &previous.rows {
+            previous_by_crn
+                .entry(old[previous_crn_column].to_string())
+                .or_insert(old);
+        }
+        let crns: std::collections::HashSet<String> = self
+            .rows
+            .iter()
+            .map(|row| row[crn_column].to_string())
+            .collect();
+
+        let mut rows = vec![];
+        for row in &self.rows {
+            let Some(old) = previous_by_crn.get(&row[crn_column].to_string()) else {
+                rows.push(marked("+", row.clone()));
+                continue;
+            };
+            let old = previous_row(old);
+            if old
+                .iter()
+                .zip(row)
+                .all(|(old, new)| old.to_string() == new.to_string())
+            {
+                continue;
+            }
+            let changed = old
+                .into_iter()
+                .zip(row)
+                .map(|(old, new)| {
+                    if old.to_string() == new.to_string() {
+                        new.clone()
+                    } else {
+                        Value::Text(format!("{old} → {new}"))
+                    }
+                })
+                .collect();
+            rows.push(marked("~", changed));
+        }
+        for old in &previous.rows {
+            if !crns.contains(&old[previous_crn_column].to_string()) {
+                rows.push(marked("-", previous_row(old)));
+            }
+        }
+
+        Some(ResultSet {
+            columns: std::iter::once(CHANGE_COLUMN.to_string())
+                .chain(self.columns.iter().cloned())
+                .collect(),
+            rows,
+        })
+    }
+
+    /// The distinct values of the CRN column, in order: the column named `crn` (ignoring case),
+    /// or else the first whose name contains it. `None` if there's no such column.
+    pub fn crns(&self) -> Option<Vec<String>> {
+        let column = self.column_index("crn").or_else(|| {
+            self.columns
+                .iter()
+                .position(|c| c.to_lowercase().contains("crn"))
+        })?;
+
+        let mut crns: Vec<String> = vec![];
+        for row in &self.rows {
+            let crn = row[column].to_string();
+            if !crn.is_empty() && !crns.contains(&crn) {
+                crns.push(crn);
+            }
+        }
+        Some(crns)
+    }
+
+    /// Collapse whichever enrollment columns are present into a single `seats` column, like
+    /// `87/110 (23 open, WL 5/10)`, in place of the first of them. Results with fewer than two
+    /// enrollment columns are returned as-is.
+    pub fn compact_enrollment(&self) -> ResultSet {
+        const ENROLLMENT_COLUMNS: [&str; 6] = [
+            "enrollment",
+            "max_enrollment",
+            "seats_available",
+            "waitlist_count",
+            "waitlist_capacity",
+            "waitlist_available",
+        ];
+
+        let positions = ENROLLMENT_COLUMNS.map(|name| self.columns.iter().position(|c| c == name));
+        let present: Vec<usize> = positions.iter().flatten().copied().collect();
+        if present.len() < 2 {
+            return self.clone();
+        }
+        let insert_at = *present.iter().min().expect("two columns are present");
+
+        let mut compacted = ResultSet {
+            columns: vec![],
+            rows: vec![],
+        };
+        for (i, column) in self.columns.iter().enumerate() {
+            if i == insert_at {
+                compacted.columns.push("seats".to_string());
+            }
+            if !present.contains(&i) {
+                compacted.columns.push(column.clone());
+            }
+        }
+
+        for row in &self.rows {
+            let [enrolled, max, open, wl_count, wl_capacity, wl_open] =
+                positions.map(|i| i.map(|i| &row[i]).filter(|value| **value != Value::Null));
+
+            let main = match (enrolled, max) {
+                (Some(enrolled), Some(max)) => Some(format!("{enrolled}/{max}")),
+                (Some(enrolled), None) => Some(format!("{enrolled} enrolled")),
+                (None, Some(max)) => Some(format!("{max} max")),
+                (None, None) => None,
+            };
+            let mut details = vec![];
+            if let Some(open) = open {
+                details.push(format!("{open} open"));
+            }
+            match (wl_count, wl_capacity, wl_open) {
+                (Some(count), Some(capacity), _) => details.push(format!("WL {count}/{capacity}")),
+                (Some(count), None, _) => details.push(format!("WL {count}")),
+                (None, _, Some(wl_open)) => details.push(format!("WL {wl_open} open")),
+                (None, Some(capacity), None) => details.push(format!("WL 0/{capacity}")),
+                (None, None, None) => {}
+            }
+
+            let seats = match main {
+                Some(main) if details.is_empty() => main,
+                Some(main) => format!("{main} ({})", details.join(", ")),
+                None => details.join(", "),
+            };
+
+            let mut compacted_row = vec![];
+            for (i, value) in row.iter().enumerate() {
+                if i == insert_at {
+                    compacted_row.push(Value::Text(seats.clone()));
+                }
+                if !present.contains(&i) {
+                    compacted_row.push(value.clone());
+                }
+            }
+            compacted.rows.push(compacted_row);
+        }
+
+        compacted
+    }
+
+    /// Stably sort the rows by the named columns, ignoring case. A `-` prefix or a `:desc` suffix
+    /// sorts that column in descending order (`:asc` is the default). Returns the names that
+    /// aren't columns, and doesn't sort if there are any.
+    pub fn sort_by_columns(&mut self, names: &[String]) -> Vec<String> {
+        let keys: Vec<(&str, bool)> = names
+            .iter()
+            .map(|name| match name.strip_prefix('-') {
+                Some(name) => (name, true),
+                None => match name.rsplit_once(':') {
+                    Some((name, order)) if order.eq_ignore_ascii_case("desc") => (name, true),
+                    Some((name, order)) if order.eq_ignore_ascii_case("asc") => (name, false),
+                    _ => (name.as_str(), false),
+                },
+            })
+            .collect();
+        let missing: Vec<String> = keys
+            .iter()
+            .filter(|(name, _)| !self.columns.iter().any(|c| c.eq_ignore_ascii_case(name)))
+            .map(|(name, _)| name.to_string())
+            .collect();
+        if !missing.is_empty() {
+            return missing;
+        }
+
+        let keys: Vec<(usize, bool)> = keys
+            .iter()
+            .filter_map(|(name, descending)| {
+                let i = self.column_index(name)?;
+                Some((i, *descending))
+            })
+            .collect();
+        self.rows.sort_by(|a, b| {
+            keys.iter()
+                .map(|&(i, descending)| {
+                    let ordering = compare_values(&a[i], &b[i]);
+                    if descending {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        vec![]
+    }
+
+    /// Split the rows into runs with equal values in the named columns, ignoring case. Returns
+    /// each run's range of rows, and its key values in the order of `names`.
+    fn groups(&self, names: &[String]) -> Vec<(std::ops::Range<usize>, Vec<Value>)> {
+        let keys: Vec<usize> = names
+            .iter()
+            .filter_map(|name| self.column_index(name))
+            .collect();
+        let key = |row: &[Value]| keys.iter().map(|&i| row[i].clone()).collect::<Vec<_>>();
+
+        let mut groups: Vec<(std::ops::Range<usize>, Vec<Value>)> = vec![];
+        for (i, row) in self.rows.iter().enumerate() {
+            let row_key = key(row);
+            match groups.last_mut() {
+                Some((range, group_key)) if *group_key == row_key => range.end = i + 1,
+                _ => groups.push((i..i + 1, row_key)),
+            }
+        }
+        groups
+    }
+
+    /// Turn the distinct values of the `column` column into columns of their own, in sorted
+    /// order, with one row for each distinct set of the other columns' values. Each cell has the
+    /// number of rows with that value, or with `value`, that column's value instead: summed if
+    /// several rows are numbers, or else joined with commas. Returns the names that aren't
+    /// columns, and doesn't pivot if there are any.
+    pub fn pivot(&mut self, column: &str, value: Option<&str>) -> Vec<String> {
+        let position = |name: &str| self.column_index(name);
+        let missing: Vec<String> = std::iter::once(column)
+            .chain(value)
+            .filter(|name| position(name).is_none())
+            .map(str::to_string)
+            .collect();
+        if !missing.is_empty() {
+            return missing;
+        }
+        let pivot = position(column).expect("column exists");
+        let value = value.map(|name| position(name).expect("column exists"));
+        let key_columns: Vec<usize> = (0..self.columns.len())
+            .filter(|&i| i != pivot && Some(i) != value)
+            .collect();
+
+        // First, the new columns.
+        let mut headings: Vec<Value> = vec![];
+        for row in &self.rows {
+            if !headings.contains(&row[pivot]) {
+                headings.push(row[pivot].clone());
+            }
+        }
+        headings.sort_by(compare_values);
+
+        // Then a row for each key, in the order they first appear, filling in its cells.
+        let empty = match value {
+            Some(_) => Value::Null,
+            None => Value::Integer(0),
+        };
+        let mut rows: Vec<Vec<Value>> = vec![];
+        for row in &self.rows {
+            let key: Vec<Value> = key_columns.iter().map(|&i| row[i].clone()).collect();
+            let i = match rows
+                .iter()
+                .position(|pivoted| pivoted[..key.len()] == key[..])
+            {
+                Some(i) => i,
+                None => {
+                    let cells = std::iter::repeat_n(empty.clone(), headings.len());
+                    rows.push(key.iter().cloned().chain(cells).collect());
+                    rows.len() - 1
+                }
+            };
+            let heading = headings
+                .iter()
+                .position(|heading| *heading == row[pivot])
+                .expect("every value is a heading");
+            let cell = &mut rows[i][key.len() + heading];
+            *cell = match (value.map(|i| &row[i]), &*cell) {
+                (None, Value::Integer(n)) => Value::Integer(n + 1),
+                (Some(new), Value::Null) => new.clone(),
+                (Some(Value::Null), _) => continue,
+                (Some(Value::Integer(new)), Value::Integer(old)) => Value::Integer(old + new),
+                (Some(Value::Integer(new)), Value::Real(old)) => Value::Real(old + *new as f64),
+                (Some(Value::Real(new)), Value::Integer(old)) => Value::Real(*old as f64 + new),
+                (Some(Value::Real(new)), Value::Real(old)) => Value::Real(old + new),
+                (Some(new), old) => Value::Text(format!("{old}, {new}")),
+                (None, _) => unreachable!("counts are integers"),
+            };
+        }
+
+        self.columns = key_columns
+            .iter()
+            .map(|&i| self.columns[i].clone())
+            .chain(headings.iter().map(|heading| match heading {
+                Value::Null => "(none)".to_string(),
+                heading => heading.to_string(),
+            }))
+            .collect();
+        self.rows = rows;
+        vec![]
+    }
+
+    /// Add a leading `#` column with 1-based row numbers.
+    pub fn number_rows(&mut self) {
+        self.columns.insert(0, "#".to_string());
+        for (i, row) in self.rows.iter_mut().enumerate() {
+            row.insert(0, Value::Integer(i as i64 + 1));
+        }
+    }
+
+    /// Whether every non-NULL value in a column is a number.
+    pub fn is_numeric_column(&self, column: usize) -> bool {
+        let mut values = self
+            .rows
+            .iter()
+            .map(|row| &row[column])
+            .filter(|value| **value != Value::Null)
+            .peekable();
+
+        values.peek().is_some()
+            && values.all(|value| matches!(value, Value::Integer(_) | Value::Real(_)))
+    }
+}
+
+/// Order values for sorting: NULLs first, then numbers (numerically), then text
+/// (case-insensitively, with case breaking ties so equal values stay together), then bytes.
+fn compare_values(a: &Value, b: &Value) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    let number = |value: &Value| match value {
+        Value::Integer(x) => Some(*x as f64),
+        Value::Real(x) => Some(*x),
+        _ => None,
+    };
+    match (a, b) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => Ordering::Less,
+        (_, Value::Null) => Ordering::Greater,
+        // Like SQLite, bytes sort after everything else.
+        (Value::Blob(a), Value::Blob(b)) => a.cmp(b),
+        (Value::Blob(_), _) => Ordering::Greater,
+        (_, Value::Blob(_)) => Ordering::Less,
+        (Value::Text(a), Value::Text(b)) => a
+            .to_lowercase()
+            .cmp(&b.to_lowercase())
+            .then_with(|| a.cmp(b)),
+        (Value::Text(_), _) => Ordering::Greater,
+        (_, Value::Text(_)) => Ordering::Less,
+        _ => number(a).partial_cmp(&number(b)).unwrap_or(Ordering::Equal),
+    }
+}
+
+/// Decode a cell using the storage class SQLite reports for the value.
+fn decode(row: &SqliteRow, index: usize, name: &str) -> Result<Value> {
+    let raw = row.try_get_raw(index)?;
+    if raw.is_null() {
+        return Ok(Value::Null);
+    }
+
+    let value = match raw.type_info().name() {
+        "INTEGER" => Value::Integer(row.try_get(index)?),
+        "REAL" => Value::Real(row.try_get(index)?),
+        "BLOB" => Value::Blob(row.try_get(index)?),
+        _ => Value::Text(
+            row.try_get(index)
+                .wrap_err_with(|| format!("Failed to decode column `{name}`"))?,
+        ),
+    };
+
+    Ok(value)
+}
+
+/// Render query results in the requested format. `sql` is the query that produced them, in
+/// answer to `question`.
+pub fn render(
+    results: &ResultSet,
+    question: &str,
+    sql: &str,
+    options: &RenderOptions,
+) -> Result<String> {
+    // Machine-readable formats keep the raw enrollment columns, unless asked otherwise.
+    let compacted;
+    let results = match options.compact_enrollment {
+        // Tables compact their own results, after using the raw columns to pick row colors.
+        _ if options.format == Format::Table => results,
+        Some(CompactEnrollment::All) => {
+            compacted = results.compact_enrollment();
+            &compacted
+        }
+        Some(CompactEnrollment::Display) if options.format.is_for_display() => {
+            compacted = results.compact_enrollment();
+            &compacted
+        }
+        _ => results,
+    };
+
+    let total_rows = results.rows.len();
+    let limited;
+    let results = if options.limit > 0 && total_rows > options.limit {
+        limited = ResultSet {
+            columns: results.columns.clone(),
+            rows: results.rows[..options.limit].to_vec(),
+        };
+        &limited
+    } else {
+        results
+    };
+    let footer = more_rows_footer(total_rows - results.rows.len());
+
+    // CSV and TSV have nowhere to put a footer without breaking parsers, so the caller reports
+    // truncation for them.
+    match options.format {
+        Format::Table => Ok(table(results, options) + &footer),
+        Format::Json | Format::JsonPretty => json(results, sql, total_rows, options),
+        Format::Csv => csv(results, options),
+        Format::Tsv => Ok(tsv(results, options)),
+        // A blank line keeps the footer from being read as another table row.
+        Format::Markdown if footer.is_empty() => Ok(markdown(results, options)),
+        Format::Markdown => Ok(markdown(results, options) + "\n" + &footer),
+        Format::Html if footer.is_empty() => Ok(html(results, question, sql, options)),
+        Format::Html => Ok(html(results, question, sql, options).replacen(
+            "</table>",
+            &format!("</table>\n<p>{}</p>", footer.trim()),
+            1,
+        )),
+    }
+}
+
+/// Footer noting how many rows `--limit` left out, starting with a newline. Empty if none.
+pub fn more_rows_footer(omitted: usize) -> String {
+    match omitted {
+        0 => String::new(),
+        1 => "\n… and 1 more row (use --limit 0 for all)".to_string(),
+        _ => format!("\n… and {omitted} more rows (use --limit 0 for all)"),
+    }
+}
+
+/// The name in `names` closest to `name`, ignoring case, if any is close enough to be a likely
+/// typo of it.
+pub fn closest_name<'a>(name: &str, names: &'a [String]) -> Option<&'a str> {
+    let name = name.to_lowercase();
+    names
+        .iter()
+        .map(|candidate| (edit_distance(&name, &candidate.to_lowercase()), candidate))
+        .filter(|(distance, candidate)| *distance <= (candidate.chars().count() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+/// The Levenshtein distance between two strings, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Render query results as a table.
+pub fn table(results: &ResultSet, options: &RenderOptions) -> String {
+    let colors: Vec<Option<&str>> = results
+        .rows
+        .iter()
+        .map(|row| {
+            options
+                .color
+                .and_then(|scheme| results.availability_color(row, scheme))
+        })
+        .collect();
+
+    // Before compacting or grouping, which can drop the `term` column.
+    let links = options
+        .hyperlink_url
+        .as_deref()
+        .and_then(|url| results.section_links(url));
+
+    let compacted;
+    let results = if options.compact_enrollment.is_some() {
+        compacted = results.compact_enrollment();
+        &compacted
+    } else {
+        results
+    };
+
+    // Group headings, keyed by the first row of each group.
+    let mut headings = std::collections::HashMap::new();
+    let ungrouped;
+    let results = if options.group_by.is_empty() {
+        results
+    } else {
+        for (range, key) in results.groups(&options.group_by) {
+            headings.insert(range.start, group_heading(results, range, &key));
+        }
+        let mut stripped = results.clone();
+        stripped.hide_columns(&options.group_by);
+        ungrouped = stripped;
+        &ungrouped
+    };
+
+    // Blank header cells don't affect column widths.
+    let mut header = if options.no_header {
+        vec![String::new(); results.columns.len()]
+    } else {
+        results.columns.clone()
+    };
+    let mut rows: Vec<Vec<String>> = results
+        .rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .zip(&results.columns)
+                .map(|(value, column)| {
+                    let cell = truncate(&cell_text(column, value, options), options.max_col_width);
+                    wrap_words(&cell, options.wrap_width)
+                })
+                .collect()
+        })
+        .collect();
+
+    let too_wide =
+        |max_width| table_width(&column_widths(&header, &rows), options.table_style) > max_width;
+    let expand = match options.expanded {
+        Expanded::Always => true,
+        Expanded::Auto => options.max_width.is_some_and(too_wide),
+        Expanded::Never => false,
+    };
+    if expand {
+        // Records have room for all of `raw`.
+        if options.raw_depth.is_some() {
+            for (cells, row) in rows.iter_mut().zip(&results.rows) {
+                for ((cell, value), column) in cells.iter_mut().zip(row).zip(&results.columns) {
+                    if let (Value::Text(text), "raw") = (value, column.as_str()) {
+                        if let Some(json) = parse_json(text) {
+                            *cell = json_text(&json, 0, Some(0));
+                        }
+                    }
+                }
+            }
+        }
+        return expanded(&results.columns, &rows);
+    }
+
+    if let Some(max_width) = options.max_width {
+        fit_to_width(
+            results,
+            &mut header,
+            &mut rows,
+            max_width,
+            options.wrap_to_fit,
+            options.table_style,
+        );
+    }
+    let widths = column_widths(&header, &rows);
+
+    // Highlight after fitting, so escape codes don't count towards widths, and before coloring
+    // rows, which would end at the highlight's reset otherwise (and whose codes have digits a
+    // term could match). Not CRNs that will be links, which are found by their text.
+    if !options.highlight_terms.is_empty() {
+        let link_column = links
+            .is_some()
+            .then(|| results.column_index("crn"))
+            .flatten();
+        for (cells, row) in rows.iter_mut().zip(&results.rows) {
+            for (i, (cell, value)) in cells.iter_mut().zip(row).enumerate() {
+                if matches!(value, Value::Text(_)) && Some(i) != link_column {
+                    *cell = highlight(cell, &options.highlight_terms);
+                }
+            }
+        }
+    }
+
+    // Color rows after fitting, too.
+    for (cells, color) in rows.iter_mut().zip(colors) {
+        if let Some(code) = color {
+            for cell in cells.iter_mut() {
+                *cell = colorize(cell, code);
+            }
+        }
+    }
+
+    // Right-align numeric columns (NULLs aside), headers included, so digits line up.
+    let alignments: Vec<Alignment> = (0..results.columns.len())
+        .map(|i| {
+            if results.is_numeric_column(i) {
+                Alignment::Right
+            } else {
+                Alignment::Left
+            }
+        })
+        .collect();
+
+    if !options.no_header && options.color.is_some() {
+        for cell in header.iter_mut() {
+            *cell = colorize(cell, HEADER_STYLE);
+        }
+    }
+
+    let crn_column = results.column_index("crn");
+    let links = links.as_deref().zip(crn_column);
+    if let TableStyle::Plain | TableStyle::Borderless = options.table_style {
+        let rule = options.table_style == TableStyle::Plain;
+        let header = (!options.no_header).then_some(header);
+        return spaced_columns(header, rows, headings, &widths, &alignments, rule, links);
+    }
+
+    // Links are attached to their cells now, but as placeholders the width of the CRN, since
+    // term_table would count their escape codes as text. They're filled in after layout.
+    let mut placed_links = vec![];
+    if let Some((links, column)) = links {
+        for (cells, (crn, url)) in rows.iter_mut().zip(links) {
+            let cell = &mut cells[column];
+            if cell.contains(crn.as_str()) {
+                *cell = cell.replacen(crn, &link_placeholder(crn), 1);
+                placed_links.push(hyperlink(crn, url));
+            }
+        }
+    }
+
+    let mut table = term_table::Table::new();
+    table.style = match options.table_style {
+        TableStyle::Ascii => term_table::TableStyle::simple(),
+        _ => term_table::TableStyle::rounded(),
+    };
+    table.separate_rows = true;
+
+    let cells = |row: Vec<String>| {
+        row.into_iter()
+            .zip(&alignments)
+            .map(|(cell, &alignment)| TableCell::new_with_alignment(cell, 1, alignment))
+            .collect::<Vec<_>>()
+    };
+
+    // Only the header and group headings are separated from the rows below them.
+    if !options.no_header {
+        table.rows.push(term_table::row::Row::new(cells(header)));
+    }
+    for (i, row) in rows.into_iter().enumerate() {
+        let heading = headings.remove(&i);
+        let mut row = term_table::row::Row::new(cells(row));
+        row.has_separator = i == 0 || heading.is_some();
+
+        if let Some(heading) = heading {
+            let span = results.columns.len().max(1);
+            table
+                .rows
+                .push(term_table::row::Row::new([TableCell::new_with_col_span(
+                    heading, span,
+                )]));
+        }
+        table.rows.push(row);
+    }
+
+    fill_link_placeholders(&table.render(), &placed_links)
+}
+
+/// Lay out a table without borders, with columns two spaces apart, and a dashed line under the
+/// header if `rule` is set. Group `headings` go on a line of their own, with a blank line between
+/// groups.
+/// `links` are the rows' (CRN, URL) pairs and the CRN column, for `--hyperlinks`.
+fn spaced_columns(
+    header: Option<Vec<String>>,
+    rows: Vec<Vec<String>>,
+    mut headings: std::collections::HashMap<usize, String>,
+    widths: &[usize],
+    alignments: &[Alignment],
+    rule: bool,
+    links: Option<(&[(String, String)], usize)>,
+) -> String {
+    let mut lines = vec![];
+    let push_row = |lines: &mut Vec<String>, row: &[String], link: Option<(usize, &str, &str)>| {
+        let height = row
+            .iter()
+            .map(|cell| cell.lines().count())
+            .max()
+            .unwrap_or(0);
+        for i in 0..height.max(1) {
+            let line: Vec<String> = row
+                .iter()
+                .zip(widths.iter().zip(alignments))
+                .enumerate()
+                .map(|(column, (cell, (&width, &alignment)))| {
+                    let text = cell.lines().nth(i).unwrap_or("");
+                    let padding = " ".repeat(width.saturating_sub(visible_width(text)));
+                    // Only the first line of a wrapped row has its CRN.
+                    let text = match link {
+                        Some((link_column, crn, url)) if link_column == column && i == 0 => {
+                            text.replacen(crn, &hyperlink(crn, url), 1)
+                        }
+                        _ => text.to_string(),
+                    };
+                    match alignment {
+                        Alignment::Right => padding + &text,
+                        _ => text + &padding,
+                    }
+                })
+                .collect();
+            lines.push(line.join("  ").trim_end().to_string());
+        }
+    };
+
+    if let Some(header) = header {
+        push_row(&mut lines, &header, None);
+        if rule {
+            let dashes: Vec<String> = widths.iter().map(|&width| "-".repeat(width)).collect();
+            lines.push(dashes.join("  "));
+        }
+    }
+    for (i, row) in rows.iter().enumerate() {
+        if let Some(heading) = headings.remove(&i) {
+            if i > 0 {
+                lines.push(String::new());
+            }
+            lines.push(heading);
+        }
+        let link = links.map(|(links, column)| (column, links[i].0.as_str(), links[i].1.as_str()));
+        push_row(&mut lines, row, link);
+    }
+
+    lines.join("\n")
+}
+
+/// `text` as an OSC 8 hyperlink to `url`.
+fn hyperlink(text: &str, url: &str) -> String {
+    format!("\x1b]8;;{url}\x1b\\{text}\x1b]8;;\x1b\\")
+}
+
+/// The width of `text` on screen, not counting ANSI color codes or OSC 8 hyperlinks.
+fn visible_width(text: &str) -> usize {
+    let mut visible = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            // Color codes end at their first letter.
+            ('\x1b', Some('[')) => {
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+            // OSC sequences end with `ESC \`.
+            ('\x1b', Some(']')) => {
+                while let Some(c) = chars.next() {
+                    if c == '\x1b' && chars.next_if_eq(&'\\').is_some() {
+                        break;
+                    }
+                }
+            }
+            _ => visible.push(c),
+        }
+    }
+    visible.width()
+}
+
+/// ANSI style for table headers: bold and underlined.
+const HEADER_STYLE: &str = "1;4";
+
+/// Words too common in questions to be worth highlighting in their results.
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "all", "an", "and", "any", "are", "at", "by", "can", "class", "classes",
+    "course", "courses", "do", "does", "for", "from", "get", "give", "has", "have", "how", "i",
+    "in", "is", "it", "list", "many", "me", "my", "of", "on", "or", "section", "sections", "show",
+    "some", "taught", "teach", "teaches", "that", "the", "there", "this", "to", "what", "when",
+    "where", "which", "who", "with",
+];
+
+/// The words in `question` worth highlighting in its results, lowercase and longest first: all
+/// but single characters and the common ones in `STOP_WORDS`.
+pub fn highlight_terms(question: &str) -> Vec<String> {
+    let mut terms: Vec<String> = vec![];
+    for word in question.split(|c: char| !c.is_alphanumeric()) {
+        let word = word.to_lowercase();
+        if word.chars().count() > 1
+            && !STOP_WORDS.contains(&word.as_str())
+            && !terms.contains(&word)
+        {
+            terms.push(word);
+        }
+    }
+    // So a longer term wins where one contains another.
+    terms.sort_by_key(|term| std::cmp::Reverse(term.chars().count()));
+    terms
+}
+
+/// Bold and underline every occurrence of `terms` in `text`, ignoring case. The codes only turn
+/// off bold and underline afterwards, so a row's color carries on.
+fn highlight(text: &str, terms: &[String]) -> String {
+    let mut highlighted = String::new();
+    let mut rest = text;
+    'text: while let Some(c) = rest.chars().next() {
+        for term in terms {
+            if let Some(len) = prefix_len_ignoring_case(rest, term) {
+                highlighted.push_str(&format!("\x1b[1;4m{}\x1b[22;24m", &rest[..len]));
+                rest = &rest[len..];
+                continue 'text;
+            }
+        }
+        highlighted.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    highlighted
+}
+
+/// The length in bytes of the start of `text` that's `prefix`, ignoring case, if it starts with
+/// it.
+fn prefix_len_ignoring_case(text: &str, prefix: &str) -> Option<usize> {
+    let mut chars = text.char_indices();
+    for expected in prefix.chars() {
+        let (_, c) = chars.next()?;
+        if !c.to_lowercase().eq(expected.to_lowercase()) {
+            return None;
+        }
+    }
+    Some(chars.next().map_or(text.len(), |(i, _)| i))
+}
+
+/// Stands in for each character of a linked CRN while a table is laid out. It's in Unicode's
+/// private use area, so it won't be in any value.
+const LINK_PLACEHOLDER: char = '\u{E000}';
+
+/// A placeholder as wide as `crn`, for `fill_link_placeholders` to replace with its link.
+fn link_placeholder(crn: &str) -> String {
+    crn.chars().map(|_| LINK_PLACEHOLDER).collect()
+}
+
+/// Replace each placeholder in a rendered table with the next of `links`, in order.
+fn fill_link_placeholders(table: &str, links: &[String]) -> String {
+    let mut links = links.iter();
+    let mut filled = String::new();
+    let mut chars = table.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == LINK_PLACEHOLDER {
+            while chars.next_if_eq(&LINK_PLACEHOLDER).is_some() {}
+            filled.push_str(links.next().map_or("", String::as_str));
+        } else {
+            filled.push(c);
+        }
+    }
+    filled
+}
+
+/// Wrap each line of `text` in an ANSI color, so table borders between lines stay uncolored.
+fn colorize(text: &str, code: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("\x1b[{code}m{line}\x1b[0m")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The text of a table cell. Values in boolean columns become symbols, unless `raw_values` is set.
+fn cell_text(column: &str, value: &Value, options: &RenderOptions) -> String {
+    let boolean = if options.raw_values || !options.bool_columns.iter().any(|c| c == column) {
+        None
+    } else {
+        value.as_bool()
+    };
+
+    match (boolean, options.bool_style, options.ascii) {
+        (Some(true), BoolStyle::Words, _) => "Yes".to_string(),
+        (Some(false), BoolStyle::Words, _) => "No".to_string(),
+        (Some(true), BoolStyle::Symbols, false) => "✓".to_string(),
+        (Some(false), BoolStyle::Symbols, false) => "✗".to_string(),
+        (Some(true), BoolStyle::Symbols, true) => "Y".to_string(),
+        (Some(false), BoolStyle::Symbols, true) => "N".to_string(),
+        (None, _, _) => match (value, options.raw_depth) {
+            // Tables have room for it indented.
+            (Value::Text(text), Some(depth)) if column == "raw" => match parse_json(text) {
+                Some(json) => json_text(&json, depth, Some(0)),
+                None => text.clone(),
+            },
+            _ => display_text(column, value, options),
+        },
+    }
+}
+
+/// The leading column of `ResultSet::diff`, marking how each row changed.
+pub const CHANGE_COLUMN: &str = "change";
+
+/// Names of GT course attribute codes, for `expand_attributes`.
+pub const ATTRIBUTES: &[(&str, &str)] = &[
+    ("ETHS", "Ethics"),
+    ("HUM", "Humanities"),
+    ("SS", "Social Sciences"),
+    ("SLS", "Serve-Learn-Sustain"),
+];
+
+/// The built-in attribute names, with `extra` names added. An extra name for a built-in code
+/// replaces it.
+pub fn attribute_names(extra: &[(String, String)]) -> Vec<(String, String)> {
+    let mut names: Vec<(String, String)> = ATTRIBUTES
+        .iter()
+        .filter(|(code, _)| {
+            !extra
+                .iter()
+                .any(|(other, _)| other.eq_ignore_ascii_case(code))
+        })
+        .map(|(code, name)| (code.to_string(), name.to_string()))
+        .collect();
+    names.extend(extra.iter().cloned());
+    names
+}
+
+/// The text of a cell in a format meant for people: like `Value`'s `Display`, but with attribute
+/// codes expanded to names if `expand_attributes` is set. Unknown codes are left as-is.
+fn display_text(column: &str, value: &Value, options: &RenderOptions) -> String {
+    match value {
+        Value::Text(codes) if options.expand_attributes && column == "attributes" => codes
+            .split(',')
+            .map(str::trim)
+            .filter(|code| !code.is_empty())
+            .map(|code| {
+                options
+                    .attribute_names
+                    .iter()
+                    .find(|(known, _)| known.eq_ignore_ascii_case(code))
+                    .map_or(code, |(_, name)| name.as_str())
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        Value::Text(text) if column == "raw" => match (options.raw_depth, parse_json(text)) {
+            (Some(depth), Some(json)) => json_text(&json, depth, None),
+            _ => text.clone(),
+        },
+        _ => value.to_string(),
+    }
+}
+
+/// `text` parsed as JSON, if it's an object or array.
+fn parse_json(text: &str) -> Option<serde_json::Value> {
+    serde_json::from_str(text)
+        .ok()
+        .filter(|json: &serde_json::Value| json.is_object() || json.is_array())
+}
+
+/// `json` as text, with objects and arrays nested more than `depth` levels deep shown as `{…}`
+/// and `[…]` (0 shows everything). Indented by `indent` if given, otherwise on one line.
+fn json_text(json: &serde_json::Value, depth: usize, indent: Option<usize>) -> String {
+    let (open, close, items): (_, _, Vec<(Option<&str>, _)>) = match json {
+        serde_json::Value::Object(map) => (
+            '{',
+            '}',
+            map.iter().map(|(k, v)| (Some(k.as_str()), v)).collect(),
+        ),
+        serde_json::Value::Array(array) => ('[', ']', array.iter().map(|v| (None, v)).collect()),
+        scalar => return scalar.to_string(),
+    };
+    if items.is_empty() {
+        return format!("{open}{close}");
+    }
+    if depth == 1 {
+        return format!("{open}…{close}");
+    }
+
+    let items = items.into_iter().map(|(key, value)| {
+        let value = json_text(value, depth.saturating_sub(1), indent.map(|n| n + 2));
+        match key {
+            Some(key) => format!("{}: {value}", serde_json::Value::from(key)),
+            None => value,
+        }
+    });
+    match indent {
+        Some(n) => {
+            let items: Vec<String> = items
+                .map(|item| format!("{}{item}", " ".repeat(n + 2)))
+                .collect();
+            format!("{open}\n{}\n{}{close}", items.join(",\n"), " ".repeat(n))
+        }
+        None => format!("{open}{}{close}", items.collect::<Vec<_>>().join(", ")),
+    }
+}
+
+/// Render rows as blocks of `column | value` lines, one per record, like `psql`'s `\x`.
+fn expanded(columns: &[String], rows: &[Vec<String>]) -> String {
+    let name_width = columns.iter().map(|c| c.width()).max().unwrap_or(0);
+    let value_width = rows
+        .iter()
+        .flatten()
+        .flat_map(|cell| cell.lines())
+        .map(UnicodeWidthStr::width)
+        .max()
+        .unwrap_or(0);
+
+    let mut lines = vec![];
+    for (i, row) in rows.iter().enumerate() {
+        let label = format!("-[ RECORD {} ]", i + 1);
+        let rule_width = (name_width + 1).max(label.width());
+        lines.push(format!(
+            "{label}{}+{}",
+            "-".repeat(rule_width - label.width()),
+            "-".repeat(value_width + 1)
+        ));
+
+        for (column, cell) in columns.iter().zip(row) {
+            // Continuation lines of multi-line values line up under the first.
+            let mut cell_lines = cell.lines();
+            let first = cell_lines.next().unwrap_or("");
+            let line = format!("{column}{} | {first}", pad(column, name_width));
+            lines.push(line.trim_end().to_string());
+            for line in cell_lines {
+                lines.push(format!("{} | {line}", " ".repeat(name_width)));
+            }
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// Spaces to pad `text` out to `width` columns.
+fn pad(text: &str, width: usize) -> String {
+    " ".repeat(width.saturating_sub(text.width()))
+}
+
+/// The width of each column: its widest line, in the header or any row.
+fn column_widths(header: &[String], rows: &[Vec<String>]) -> Vec<usize> {
+    let cell_width = |cell: &str| cell.lines().map(UnicodeWidthStr::width).max();
+    (0..header.len())
+        .map(|i| {
+            std::iter::once(header[i].as_str())
+                .chain(rows.iter().map(|row| row[i].as_str()))
+                .filter_map(cell_width)
+                .max()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+/// The width of a table with these column widths. Bordered columns have a border and a space of
+/// padding on each side; the others are two spaces apart.
+fn table_width(widths: &[usize], style: TableStyle) -> usize {
+    match style {
+        TableStyle::Rounded | TableStyle::Ascii => {
+            widths.iter().sum::<usize>() + 3 * widths.len() + 1
+        }
+        TableStyle::Plain | TableStyle::Borderless => {
+            widths.iter().sum::<usize>() + 2 * widths.len().saturating_sub(1)
+        }
+    }
+}
+
+/// A heading for a group of rows, like `CS 2110 — Computer Organization (4 sections)`: the key
+/// values, the course title if there's a `course_title` column, and the number of rows.
+fn group_heading(results: &ResultSet, rows: std::ops::Range<usize>, key: &[Value]) -> String {
+    let mut heading = key
+        .iter()
+        .map(Value::to_string)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if let Some(title) = results.columns.iter().position(|c| c == "course_title") {
+        let title = &results.rows[rows.start][title];
+        if !key.contains(title) {
+            heading += &format!(" — {title}");
+        }
+    }
+
+    let count = rows.len();
+    let noun = if count == 1 { "section" } else { "sections" };
+    heading + &format!(" ({count} {noun})")
+}
+
+/// Shrink the widest text columns, a column at a time, until the table fits in `max_width`.
+/// Shrunk cells are word-wrapped if `wrap` is set, and truncated otherwise.
+fn fit_to_width(
+    results: &ResultSet,
+    header: &mut [String],
+    rows: &mut [Vec<String>],
+    max_width: usize,
+    wrap: bool,
+    style: TableStyle,
+) {
+    // Don't shrink text columns narrower than this; past that point, it's better to overflow.
+    const MIN_COLUMN_WIDTH: usize = 8;
+
+    let mut widths = column_widths(header, rows);
+    let natural_widths = widths.clone();
+
+    while table_width(&widths, style) > max_width {
+        let widest_text_column = (0..widths.len())
+            .filter(|&i| !results.is_numeric_column(i) && widths[i] > MIN_COLUMN_WIDTH)
+            .max_by_key(|&i| widths[i]);
+
+        match widest_text_column {
+            Some(i) => widths[i] -= 1,
+            None => break,
+        }
+    }
+
+    for (i, (&width, &natural_width)) in widths.iter().zip(&natural_widths).enumerate() {
+        if width == natural_width {
+            continue;
+        }
+
+        let shrink = |cell: &str| {
+            cell.lines()
+                .map(|line| {
+                    if wrap {
+                        wrap_words(line, width)
+                    } else {
+                        truncate(line, width)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        header[i] = shrink(&header[i]);
+        for row in rows.iter_mut() {
+            row[i] = shrink(&row[i]);
+        }
+    }
+}
+
+/// Cut `text` down to `max_width` columns, ending with an ellipsis if anything was removed. A
+/// `max_width` of 0 disables truncation.
+fn truncate(text: &str, max_width: usize) -> String {
+    if max_width == 0 || text.width() <= max_width {
+        return text.to_string();
+    }
+
+    let mut truncated = String::new();
+    let mut width = 0;
+    for c in text.chars() {
+        let c_width = c.width().unwrap_or(0);
+        if width + c_width > max_width - 1 {
+            break;
+        }
+        truncated.push(c);
+        width += c_width;
+    }
+    truncated.push('…');
+    truncated
+}
+
+/// Wrap `text` onto multiple lines of at most `width` columns, breaking at spaces where possible.
+/// Words wider than `width` are split. A `width` of 0 disables wrapping.
+fn wrap_words(text: &str, width: usize) -> String {
+    if width == 0 {
+        return text.to_string();
+    }
+
+    let mut lines = vec![];
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        let mut line_width = 0;
+        for word in paragraph.split(' ') {
+            let mut word = word.to_string();
+
+            // Start a new line if the word doesn't fit on this one.
+            if line_width > 0 && line_width + 1 + word.width() > width {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+
+            // Split words that don't fit on a line by themselves.
+            while word.width() > width {
+                let mut split_at = 0;
+                let mut split_width = 0;
+                for (i, c) in word.char_indices() {
+                    let c_width = c.width().unwrap_or(0);
+                    if split_width + c_width > width {
+                        break;
+                    }
+                    split_at = i + c.len_utf8();
+                    split_width += c_width;
+                }
+                // Always make progress, even if a single character is too wide.
+                if split_at == 0 {
+                    split_at = word.chars().next().map_or(0, char::len_utf8);
+                }
+                let rest = word.split_off(split_at);
+                lines.push(word);
+                word = rest;
+            }
+
+            if line_width > 0 {
+                line.push(' ');
+                line_width += 1;
+            }
+            line_width += word.width();
+            line.push_str(&word);
+        }
+        lines.push(line);
+    }
+
+    lines.join("\n")
+}
+
+/// Render query results as CSV. NULLs are empty fields.
+fn csv(results: &ResultSet, options: &RenderOptions) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    if !options.no_header {
+        writer.write_record(&results.columns)?;
+    }
+    for row in &results.rows {
+        writer.write_record(row.iter().map(Value::to_string))?;
+    }
+
+    let bytes = writer.into_inner().wrap_err("Failed to write CSV")?;
+    let mut csv = String::from_utf8(bytes)?;
+
+    // The caller adds the final newline.
+    if csv.ends_with('\n') {
+        csv.pop();
+    }
+    Ok(csv)
+}
+
+/// Render query results as TSV, or with another field separator. Separators and newlines inside
+/// cells become spaces, so every line is exactly one row.
+fn tsv(results: &ResultSet, options: &RenderOptions) -> String {
+    let separator = options.field_separator.unwrap_or('\t');
+    let line = |cells: &mut dyn Iterator<Item = String>| {
+        cells
+            .map(|cell| cell.replace([separator, '\n', '\r'], " "))
+            .collect::<Vec<_>>()
+            .join(separator.encode_utf8(&mut [0; 4]))
+    };
+
+    let mut lines = vec![];
+    if !options.no_header {
+        lines.push(line(&mut results.columns.iter().cloned()));
+    }
+    for row in &results.rows {
+        lines.push(line(&mut row.iter().map(Value::to_string)));
+    }
+
+    lines.join("\n")
+}
+
+/// Render query results as a markdown table. Numeric columns are right-aligned.
+fn markdown(results: &ResultSet, options: &RenderOptions) -> String {
+    fn line(cells: impl Iterator<Item = String>) -> String {
+        let cells: Vec<String> = cells.collect();
+        format!("| {} |", cells.join(" | "))
+    }
+
+    let escape = |text: &str| {
+        let text = text.split(['\n', '\r']).collect::<Vec<_>>().join(" ");
+        truncate(&text, options.max_col_width).replace('|', "\\|")
+    };
+
+    let alignments = (0..results.columns.len()).map(|i| {
+        if results.is_numeric_column(i) {
+            "---:".to_string()
+        } else {
+            "---".to_string()
+        }
+    });
+
+    let mut lines = vec![];
+    if !options.no_header {
+        lines.push(line(results.columns.iter().map(|column| escape(column))));
+        lines.push(line(alignments));
+    }
+    for row in &results.rows {
+        lines.push(line(row.iter().zip(&results.columns).map(
+            |(value, column)| escape(&display_text(column, value, options)),
+        )));
+    }
+
+    lines.join("\n")
+}
+
+const HTML_STYLE: &str = "\
+table { border-collapse: collapse; font-family: sans-serif; font-size: 0.9em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }
+th { background: #f0f0f0; }
+tbody tr:nth-child(even) { background: #fafafa; }
+details { margin-bottom: 1em; }
+pre { white-space: pre-wrap; }";
+
+/// Render query results as an HTML table.
+fn html(results: &ResultSet, question: &str, sql: &str, options: &RenderOptions) -> String {
+    let style = if options.html_style {
+        format!("<style>\n{HTML_STYLE}\n</style>\n")
+    } else {
+        String::new()
+    };
+
+    // A fragment carries its own stylesheet; a full page puts it in the `<head>`.
+    let mut html = if options.html_full_page {
+        String::new()
+    } else {
+        style.clone()
+    };
+
+    if options.html_details {
+        html += &format!(
+            "<details>\n<summary>{}</summary>\n<pre><code>{}</code></pre>\n</details>\n",
+            escape_html(question),
+            escape_html(sql)
+        );
+    }
+
+    html += "<table>\n";
+    if !options.no_header {
+        html += "<thead>\n<tr>";
+        for column in &results.columns {
+            html += &format!("<th>{}</th>", escape_html(column));
+        }
+        html += "</tr>\n</thead>\n";
+    }
+    html += "<tbody>\n";
+    for row in &results.rows {
+        html += "<tr>";
+        for (column, value) in results.columns.iter().zip(row) {
+            html += &format!(
+                "<td data-column=\"{}\">{}</td>",
+                escape_html(column),
+                escape_html(&display_text(column, value, options))
+            );
+        }
+        html += "</tr>\n";
+    }
+    html += "</tbody>\n</table>";
+
+    if options.html_full_page {
+        html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n{style}</head>\n<body>\n{html}\n</body>\n</html>",
+            escape_html(question)
+        );
+    }
+
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Render query results as JSON objects keyed by column name.
+/// `total_rows` counts rows before `--limit`, for the envelope.
+fn json(
+    results: &ResultSet,
+    sql: &str,
+    total_rows: usize,
+    options: &RenderOptions,
+) -> Result<String> {
+    #[derive(serde::Serialize)]
+    struct Envelope<'a, T> {
+        rows: T,
+        truncated: bool,
+        sql: &'a str,
+        row_count: usize,
+        total_rows: usize,
+    }
+
+    /// With grouping, rows are nested under an object with the group's key values.
+    #[derive(serde::Serialize)]
+    struct Group<'a> {
+        group: JsonRow<'a>,
+        rows: Vec<JsonRow<'a>>,
+    }
+
+    let bool_columns: &[String] = if options.raw_values {
+        &[]
+    } else {
+        &options.bool_columns
+    };
+    let json_row = |columns, values| JsonRow {
+        columns,
+        values,
+        bool_columns,
+        raw_json: options.raw_depth.is_some(),
+    };
+
+    let pretty = options.format == Format::JsonPretty;
+    let rendered = if options.group_by.is_empty() {
+        let rows: Vec<JsonRow> = results
+            .rows
+            .iter()
+            .map(|values| json_row(&results.columns, values))
+            .collect();
+        if options.json_envelope {
+            let row_count = rows.len();
+            to_json(
+                &Envelope {
+                    rows,
+                    truncated: row_count < total_rows,
+                    sql,
+                    row_count,
+                    total_rows,
+                },
+                pretty,
+            )
+        } else {
+            to_json(&rows, pretty)
+        }
+    } else {
+        let groups = results.groups(&options.group_by);
+        let key_columns: Vec<String> = options
+            .group_by
+            .iter()
+            .filter_map(|name| {
+                results
+                    .columns
+                    .iter()
+                    .find(|c| c.eq_ignore_ascii_case(name))
+            })
+            .cloned()
+            .collect();
+        let mut ungrouped = results.clone();
+        ungrouped.hide_columns(&options.group_by);
+
+        let groups: Vec<Group> = groups
+            .iter()
+            .map(|(range, key)| Group {
+                group: json_row(&key_columns, key),
+                rows: ungrouped.rows[range.clone()]
+                    .iter()
+                    .map(|values| json_row(&ungrouped.columns, values))
+                    .collect(),
+            })
+            .collect();
+        if options.json_envelope {
+            to_json(
+                &Envelope {
+                    rows: groups,
+                    truncated: results.rows.len() < total_rows,
+                    sql,
+                    row_count: results.rows.len(),
+                    total_rows,
+                },
+                pretty,
+            )
+        } else {
+            to_json(&groups, pretty)
+        }
+    };
+
+    rendered.wrap_err("Failed to serialize results")
+}
+
+fn to_json<T: Serialize>(value: &T, pretty: bool) -> serde_json::Result<String> {
+    if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+}
+
+/// A row serialized as a JSON object, keeping the query's column order. Values in
+/// `bool_columns` that look like booleans are serialized as booleans.
+pub struct JsonRow<'a> {
+    pub columns: &'a [String],
+    pub values: &'a [Value],
+    pub bool_columns: &'a [String],
+
+    /// Serialize the JSON held in the `raw` column as itself, rather than as a string.
+    pub raw_json: bool,
+}
+
+impl Serialize for JsonRow<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.columns.len()))?;
+        for (column, value) in self.columns.iter().zip(self.values) {
+            match value.as_bool() {
+                Some(boolean) if self.bool_columns.contains(column) => {
+                    map.serialize_entry(column, &boolean)?
+                }
+                _ if self.raw_json && column == "raw" => match value {
+                    Value::Text(text) => match serde_json::from_str::<serde_json::Value>(text) {
+                        Ok(json) => map.serialize_entry(column, &json)?,
+                        Err(_) => map.serialize_entry(column, value)?,
+                    },
+                    _ => map.serialize_entry(column, value)?,
+                },
+                _ => map.serialize_entry(column, value)?,
+            }
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Results with these columns and rows, with integers as `Value::Integer`, `None` as NULL,
+    /// and anything else as text.
+    fn results(columns: &[&str], rows: &[&[Option<&str>]]) -> ResultSet {
+        ResultSet {
+            columns: columns.iter().map(|column| column.to_string()).collect(),
+            rows: rows
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|value| match value {
+                            None => Value::Null,
+                            Some(text) => match text.parse() {
+                                Ok(n) => Value::Integer(n),
+                                Err(_) => Value::Text(text.to_string()),
+                            },
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn group_by_with_no_rows_renders_empty_groups() {
+        let mut empty = results(&["crn", "subject"], &[]);
+        assert!(empty.sort_by_columns(&names(&["subject"])).is_empty());
+
+        let options = RenderOptions {
+            format: Format::Json,
+            group_by: names(&["subject"]),
+            ..Default::default()
+        };
+        assert_eq!(render(&empty, "", "", &options).unwrap(), "[]");
+
+        let options = RenderOptions {
+            format: Format::Table,
+            ..options
+        };
+        assert!(render(&empty, "", "", &options).is_ok());
+    }
+
+    #[test]
+    fn group_by_nests_rows_under_their_key() {
+        let mut grouped = results(
+            &["crn", "subject"],
+            &[&[Some("20002"), Some("MATH")], &[Some("10001"), Some("CS")]],
+        );
+        grouped.sort_by_columns(&names(&["subject"]));
+        let options = RenderOptions {
+            format: Format::Json,
+            group_by: names(&["subject"]),
+            ..Default::default()
+        };
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&grouped, "", "", &options).unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                { "group": { "subject": "CS" }, "rows": [{ "crn": 10001 }] },
+                { "group": { "subject": "MATH" }, "rows": [{ "crn": 20002 }] },
+            ])
+        );
+    }
+
+    #[test]
+    fn group_by_ignores_the_case_of_column_names() {
+        let mut grouped = results(
+            &["crn", "subject"],
+            &[&[Some("20002"), Some("MATH")], &[Some("10001"), Some("CS")]],
+        );
+        assert!(grouped.sort_by_columns(&names(&["SUBJECT"])).is_empty());
+        let options = RenderOptions {
+            format: Format::Json,
+            group_by: names(&["SUBJECT"]),
+            ..Default::default()
+        };
+        assert_eq!(
+            render(&grouped, "", "", &options).unwrap(),
+            r#"[{"group":{"subject":"CS"},"rows":[{"crn":10001}]},{"group":{"subject":"MATH"},"rows":[{"crn":20002}]}]"#
+        );
+    }
+
+    #[test]
+    fn distinct_by_keeps_the_first_row_with_each_value() {
+        let mut rows = results(
+            &["crn", "subject"],
+            &[
+                &[Some("1"), Some("CS")],
+                &[Some("2"), Some("MATH")],
+                &[Some("3"), Some("CS")],
+            ],
+        );
+        assert_eq!(rows.distinct_by("SUBJECT"), Some(1));
+        assert_eq!(
+            rows.rows,
+            results(&[], &[&[Some("1"), Some("CS")], &[Some("2"), Some("MATH")]]).rows
+        );
+        assert_eq!(rows.distinct_by("title"), None);
+    }
+
+    #[test]
+    fn distinct_by_with_no_rows_finds_the_column() {
+        let mut empty = results(&["crn", "subject"], &[]);
+        assert_eq!(empty.distinct_by("subject"), Some(0));
+    }
+
+    #[test]
+    fn pivot_counts_each_value() {
+        let mut rows = results(
+            &["subject", "schedule_type"],
+            &[
+                &[Some("CS"), Some("Lecture")],
+                &[Some("CS"), Some("Lab")],
+                &[Some("MATH"), Some("Lecture")],
+                &[Some("CS"), Some("Lecture")],
+            ],
+        );
+        assert!(rows.pivot("schedule_type", None).is_empty());
+        assert_eq!(rows.columns, names(&["subject", "Lab", "Lecture"]));
+        assert_eq!(
+            rows.rows,
+            results(
+                &[],
+                &[
+                    &[Some("CS"), Some("1"), Some("2")],
+                    &[Some("MATH"), Some("0"), Some("1")]
+                ]
+            )
+            .rows
+        );
+    }
+
+    #[test]
+    fn pivot_sums_values() {
+        let mut rows = results(
+            &["subject", "schedule_type", "seats"],
+            &[
+                &[Some("CS"), Some("Lecture"), Some("10")],
+                &[Some("CS"), Some("Lecture"), Some("5")],
+                &[Some("MATH"), Some("Lab"), Some("3")],
+            ],
+        );
+        assert!(rows.pivot("schedule_type", Some("seats")).is_empty());
+        assert_eq!(rows.columns, names(&["subject", "Lab", "Lecture"]));
+        assert_eq!(
+            rows.rows,
+            results(
+                &[],
+                &[
+                    &[Some("CS"), None, Some("15")],
+                    &[Some("MATH"), Some("3"), None]
+                ]
+            )
+            .rows
+        );
+    }
+
+    #[test]
+    fn pivot_with_no_rows_keeps_the_other_columns() {
+        let mut empty = results(&["subject", "schedule_type"], &[]);
+        assert!(empty.pivot("schedule_type", None).is_empty());
+        assert_eq!(empty.columns, names(&["subject"]));
+        assert!(empty.rows.is_empty());
+
+        assert_eq!(empty.pivot("term", None), names(&["term"]));
+    }
+
+    /// The values in a column, as shown.
+    fn column(results: &ResultSet, name: &str) -> Vec<String> {
+        let i = results.columns.iter().position(|c| c == name).unwrap();
+        results.rows.iter().map(|row| row[i].to_string()).collect()
+    }
+
+    #[test]
+    fn sort_orders_numbers_numerically() {
+        let mut sorted = results(
+            &["crn", "credits"],
+            &[
+                &[Some("1"), Some("10")],
+                &[Some("2"), Some("3")],
+                &[Some("3"), Some("4")],
+            ],
+        );
+        assert!(sorted.sort_by_columns(&names(&["credits"])).is_empty());
+        assert_eq!(column(&sorted, "credits"), ["3", "4", "10"]);
+
+        assert!(sorted.sort_by_columns(&names(&["-credits"])).is_empty());
+        assert_eq!(column(&sorted, "credits"), ["10", "4", "3"]);
+    }
+
+    #[test]
+    fn sort_orders_text_ignoring_case() {
+        let mut sorted = results(
+            &["title"],
+            &[&[Some("calculus")], &[Some("Biology")], &[Some("algebra")]],
+        );
+        assert!(sorted.sort_by_columns(&names(&["title"])).is_empty());
+        assert_eq!(column(&sorted, "title"), ["algebra", "Biology", "calculus"]);
+
+        assert!(sorted.sort_by_columns(&names(&["title:desc"])).is_empty());
+        assert_eq!(column(&sorted, "title"), ["calculus", "Biology", "algebra"]);
+    }
+
+    #[test]
+    fn sort_puts_nulls_first_and_keeps_ties_in_order() {
+        let mut sorted = results(
+            &["crn", "instructor"],
+            &[
+                &[Some("1"), Some("Smith")],
+                &[Some("2"), None],
+                &[Some("3"), Some("Jones")],
+                &[Some("4"), None],
+            ],
+        );
+        assert!(sorted.sort_by_columns(&names(&["instructor"])).is_empty());
+        assert_eq!(column(&sorted, "crn"), ["2", "4", "3", "1"]);
+
+        assert!(sorted.sort_by_columns(&names(&["-instructor"])).is_empty());
+        assert_eq!(column(&sorted, "crn"), ["1", "3", "2", "4"]);
+    }
+
+    #[test]
+    fn sort_by_several_columns() {
+        let mut sorted = results(
+            &["subject", "number"],
+            &[
+                &[Some("MATH"), Some("1551")],
+                &[Some("CS"), Some("2110")],
+                &[Some("MATH"), Some("1502")],
+                &[Some("CS"), Some("1331")],
+            ],
+        );
+        assert!(sorted
+            .sort_by_columns(&names(&["subject", "number:desc"]))
+            .is_empty());
+        assert_eq!(column(&sorted, "number"), ["2110", "1331", "1551", "1502"]);
+    }
+
+    #[test]
+    fn sort_reports_missing_columns_without_sorting() {
+        let mut unsorted = results(&["crn"], &[&[Some("2")], &[Some("1")]]);
+        assert_eq!(
+            unsorted.sort_by_columns(&names(&["crn", "-seats"])),
+            ["seats"]
+        );
+        assert_eq!(column(&unsorted, "crn"), ["2", "1"]);
+    }
+
+    #[test]
+    fn sort_ignores_the_case_of_column_names() {
+        let mut sorted = results(&["crn"], &[&[Some("2")], &[Some("1")]]);
+        assert!(sorted.sort_by_columns(&names(&["CRN"])).is_empty());
+        assert_eq!(column(&sorted, "crn"), ["1", "2"]);
+
+        assert!(sorted.sort_by_columns(&names(&["-Crn"])).is_empty());
+        assert_eq!(column(&sorted, "crn"), ["2", "1"]);
+    }
+
+    #[test]
+    fn sort_with_no_rows() {
+        let mut empty = results(&["crn", "title"], &[]);
+        assert!(empty.sort_by_columns(&names(&["-crn", "title"])).is_empty());
+        assert!(empty.rows.is_empty());
+        assert_eq!(empty.sort_by_columns(&names(&["seats"])), ["seats"]);
+    }
+
+    #[test]
+    fn terminals_get_tables_and_pipes_get_tsv() {
+        assert_eq!(effective_format(None, true), Format::Table);
+        assert_eq!(effective_format(None, false), Format::Tsv);
+    }
+
+    #[test]
+    fn an_explicit_format_wins_with_or_without_a_terminal() {
+        use clap::ValueEnum;
+
+        for &format in Format::value_variants() {
+            assert_eq!(effective_format(Some(format), true), format);
+            assert_eq!(effective_format(Some(format), false), format);
+        }
+    }
+
+    /// A boolean `open` column, with a value that isn't one, and a `title` that looks like one.
+    fn open_sections() -> ResultSet {
+        results(
+            &["open", "title"],
+            &[
+                &[Some("true"), Some("true")],
+                &[Some("false"), Some("Calculus")],
+                &[Some("1"), Some("Biology")],
+                &[Some("maybe"), Some("Physics")],
+            ],
+        )
+    }
+
+    /// The cells of each row as a table would show them.
+    fn cells(results: &ResultSet, options: &RenderOptions) -> Vec<Vec<String>> {
+        results
+            .rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .zip(&results.columns)
+                    .map(|(value, column)| cell_text(column, value, options))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn boolean_columns_are_symbols_in_tables() {
+        let options = RenderOptions {
+            bool_columns: names(&["open"]),
+            ..Default::default()
+        };
+        assert_eq!(
+            cells(&open_sections(), &options),
+            [
+                ["✓", "true"],
+                ["✗", "Calculus"],
+                ["✓", "Biology"],
+                ["maybe", "Physics"]
+            ]
+        );
+
+        let ascii = RenderOptions {
+            ascii: true,
+            ..options
+        };
+        let open: Vec<String> = cells(&open_sections(), &ascii)
+            .into_iter()
+            .map(|row| row[0].clone())
+            .collect();
+        assert_eq!(open, ["Y", "N", "Y", "maybe"]);
+
+        let words = RenderOptions {
+            bool_style: BoolStyle::Words,
+            ..ascii
+        };
+        let open: Vec<String> = cells(&open_sections(), &words)
+            .into_iter()
+            .map(|row| row[0].clone())
+            .collect();
+        assert_eq!(open, ["Yes", "No", "Yes", "maybe"]);
+    }
+
+    #[test]
+    fn raw_values_shows_booleans_as_stored() {
+        let options = RenderOptions {
+            bool_columns: names(&["open"]),
+            raw_values: true,
+            ..Default::default()
+        };
+        let open: Vec<String> = cells(&open_sections(), &options)
+            .into_iter()
+            .map(|row| row[0].clone())
+            .collect();
+        assert_eq!(open, ["true", "false", "1", "maybe"]);
+
+        let json = RenderOptions {
+            format: Format::Json,
+            ..options
+        };
+        assert_eq!(
+            render(&open_sections(), "", "", &json).unwrap(),
+            r#"[{"open":"true","title":"true"},{"open":"false","title":"Calculus"},{"open":1,"title":"Biology"},{"open":"maybe","title":"Physics"}]"#
+        );
+    }
+
+    #[test]
+    fn boolean_columns_are_booleans_in_json_and_stored_values_in_csv() {
+        let json = RenderOptions {
+            format: Format::Json,
+            bool_columns: names(&["open"]),
+            ..Default::default()
+        };
+        assert_eq!(
+            render(&open_sections(), "", "", &json).unwrap(),
+            r#"[{"open":true,"title":"true"},{"open":false,"title":"Calculus"},{"open":true,"title":"Biology"},{"open":"maybe","title":"Physics"}]"#
+        );
+
+        let csv = RenderOptions {
+            format: Format::Csv,
+            ..json
+        };
+        assert_eq!(
+            render(&open_sections(), "", "", &csv).unwrap(),
+            "open,title\ntrue,true\nfalse,Calculus\n1,Biology\nmaybe,Physics"
+        );
+    }
+
+    /// Sections with a numeric column, one with NULLs, and a text column.
+    fn sections() -> ResultSet {
+        results(
+            &["crn", "title", "seats_available"],
+            &[
+                &[Some("20451"), Some("Calculus II"), Some("5")],
+                &[Some("20452"), Some("Intro to CS"), None],
+                &[Some("20453"), Some("Ethics"), Some("120")],
+            ],
+        )
+    }
+
+    #[test]
+    fn numeric_columns_are_numeric_despite_nulls() {
+        let sections = sections();
+        assert!(sections.is_numeric_column(0));
+        assert!(!sections.is_numeric_column(1));
+        assert!(sections.is_numeric_column(2));
+
+        let nulls = results(&["instructor"], &[&[None], &[None]]);
+        assert!(!nulls.is_numeric_column(0));
+    }
+
+    #[test]
+    fn tables_right_align_numeric_columns_and_their_headers() {
+        let options = RenderOptions {
+            table_style: TableStyle::Plain,
+            ..Default::default()
+        };
+        assert_eq!(
+            table(&sections(), &options),
+            "  crn  title        seats_available\n\
+             -----  -----------  ---------------\n\
+             20451  Calculus II                5\n\
+             20452  Intro to CS\n\
+             20453  Ethics                   120"
+        );
+    }
+
+    #[test]
+    fn markdown_right_aligns_numeric_columns() {
+        let options = RenderOptions {
+            format: Format::Markdown,
+            ..Default::default()
+        };
+        assert_eq!(
+            render(&sections(), "", "", &options).unwrap(),
+            "\
+| crn | title | seats_available |
+| ---: | --- | ---: |
+| 20451 | Calculus II | 5 |
+| 20452 | Intro to CS |  |
+| 20453 | Ethics | 120 |"
+        );
+    }
+
+    /// `sections` as a table in this style.
+    fn styled(table_style: TableStyle) -> String {
+        let options = RenderOptions {
+            table_style,
+            ..Default::default()
+        };
+        table(&sections(), &options)
+    }
+
+    #[test]
+    fn every_table_style_shows_every_cell() {
+        use clap::ValueEnum;
+
+        for &style in TableStyle::value_variants() {
+            let rendered = styled(style);
+            for cell in ["crn", "seats_available", "20451", "Intro to CS", "120"] {
+                assert!(rendered.contains(cell), "{style:?} is missing {cell}");
+            }
+        }
+    }
+
+    #[test]
+    fn rounded_tables_have_rounded_corners() {
+        let rendered = styled(TableStyle::Rounded);
+        assert!(rendered.starts_with('╭'));
+        assert!(rendered.trim_end().ends_with('╯'));
+        assert!(rendered.contains('│'));
+    }
+
+    #[test]
+    fn ascii_tables_are_ascii() {
+        let rendered = styled(TableStyle::Ascii);
+        assert!(rendered.is_ascii());
+        assert!(rendered.starts_with('+'));
+        assert!(rendered.contains('|'));
+    }
+
+    // `tables_right_align_numeric_columns_and_their_headers` has the plain style.
+    #[test]
+    fn borderless_tables_have_no_lines() {
+        assert_eq!(
+            styled(TableStyle::Borderless),
+            "  crn  title        seats_available\n\
+             20451  Calculus II                5\n\
+             20452  Intro to CS\n\
+             20453  Ethics                   120"
+        );
+    }
+
+    #[test]
+    fn terminals_get_rounded_tables_unless_asked_otherwise() {
+        assert_eq!(
+            effective_table_style(None, false, true),
+            TableStyle::Rounded
+        );
+        assert_eq!(effective_table_style(None, true, true), TableStyle::Ascii);
+        assert_eq!(effective_table_style(None, false, false), TableStyle::Ascii);
+        assert_eq!(
+            effective_table_style(Some(TableStyle::Plain), true, false),
+            TableStyle::Plain
+        );
+        assert_eq!(
+            effective_table_style(Some(TableStyle::Rounded), true, false),
+            TableStyle::Rounded
+        );
+    }
+
+    #[test]
+    fn highlighting_leaves_row_colors_alone() {
+        let sections = results(
+            &["title", "seats_available"],
+            &[&[Some("Room 32"), Some("5")]],
+        );
+        let options = RenderOptions {
+            table_style: TableStyle::Borderless,
+            no_header: true,
+            color: Some(ColorScheme::Default),
+            highlight_terms: names(&["32"]),
+            ..Default::default()
+        };
+        assert_eq!(
+            table(&sections, &options),
+            "\x1b[32mRoom \x1b[1;4m32\x1b[22;24m\x1b[0m  \x1b[32m5\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn diff_marks_new_gone_and_changed_rows() {
+        let previous = results(
+            &["crn", "seats"],
+            &[
+                &[Some("1"), Some("5")],
+                &[Some("2"), Some("3")],
+                &[Some("3"), Some("0")],
+            ],
+        );
+        let current = results(
+            &["crn", "seats"],
+            &[
+                &[Some("1"), Some("5")],
+                &[Some("2"), Some("1")],
+                &[Some("4"), Some("9")],
+            ],
+        );
+        let diff = current.diff(&previous).unwrap();
+        assert_eq!(diff.columns, ["change", "crn", "seats"]);
+        let rows: Vec<Vec<String>> = diff
+            .rows
+            .iter()
+            .map(|row| row.iter().map(Value::to_string).collect())
+            .collect();
+        assert_eq!(
+            rows,
+            [["~", "2", "3 → 1"], ["+", "4", "9"], ["-", "3", "0"]]
+        );
+    }
+
+    #[test]
+    fn diff_without_changes_or_crns() {
+        let sections = results(&["crn", "seats"], &[&[Some("1"), Some("5")]]);
+        assert!(sections.diff(&sections).unwrap().rows.is_empty());
+
+        let no_crns = results(&["seats"], &[&[Some("5")]]);
+        assert!(no_crns.diff(&sections).is_none());
+        assert!(sections.diff(&no_crns).is_none());
+    }
+
+    #[test]
+    fn crn_columns_are_found_ignoring_case() {
+        let previous = results(&["CRN", "Campus"], &[&[Some("1"), Some("Atlanta")]]);
+        let current = results(
+            &["CRN", "Campus"],
+            &[&[Some("1"), Some("Atlanta")], &[Some("2"), Some("Online")]],
+        );
+        let diff = current.diff(&previous).unwrap();
+        assert_eq!(diff.rows.len(), 1);
+
+        let mut current = current;
+        assert_eq!(current.exclude_online(), 1);
+        assert_eq!(current.dedup_crns(), Some(0));
+    }
+
+    #[test]
+    fn links_stay_on_crns_past_borders_in_values() {
+        let sections = results(
+            &["title", "crn", "term"],
+            &[
+                &[Some("Either │ or"), Some("12345"), Some("202402")],
+                &[Some("A | B"), Some("23456"), Some("202402")],
+            ],
+        );
+        for table_style in [TableStyle::Rounded, TableStyle::Ascii] {
+            let options = RenderOptions {
+                hyperlink_url: Some("https://oscar/{term}/{crn}".to_string()),
+                table_style,
+                ..Default::default()
+            };
+            let table = table(&sections, &options);
+            assert!(table.contains("Either │ or") && table.contains("A | B"));
+            for crn in ["12345", "23456"] {
+                let url = format!("https://oscar/202402/{crn}");
+                assert!(
+                    table.contains(&hyperlink(crn, &url)),
+                    "{crn} unlinked:\n{table}"
+                );
+            }
+            assert!(!table.contains(LINK_PLACEHOLDER));
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use eyre::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::error::{ErrorKind, WithKind};
+
+/// A question and the SQL that answered it, saved with `--save-query`.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct SavedQuery {
+    pub question: String,
+    pub sql: String,
+}
+
+/// Saved queries, stored as a JSON object keyed by name.
+pub type SavedQueries = BTreeMap<String, SavedQuery>;
+
+/// `$XDG_CONFIG_HOME/gt-reg/saved-queries.json`, or under `~/.config` by default.
+fn path() -> Result<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .ok_or_else(|| eyre::eyre!("Can't find a config directory; set HOME or XDG_CONFIG_HOME"))?;
+    Ok(config_dir.join("gt-reg").join("saved-queries.json"))
+}
+
+/// Every saved query.
+pub fn load() -> Result<SavedQueries> {
+    let path = path()?;
+    if !path.exists() {
+        return Ok(SavedQueries::new());
+    }
+
+    let text = std::fs::read_to_string(&path)
+        .wrap_err_with(|| format!("Failed to read saved queries from {}", path.display()))?;
+    serde_json::from_str(&text)
+        .wrap_err_with(|| format!("Saved queries file {} is invalid", path.display()))
+}
+
+/// The saved query called `name`.
+pub fn get(name: &str) -> Result<SavedQuery> {
+    let queries = load()?;
+    match queries.get(name) {
+        Some(query) => Ok(query.clone()),
+        None if queries.is_empty() => Err(eyre::eyre!(
+            "No saved query named `{name}`; save one with --save-query"
+        ))
+        .kind(ErrorKind::Usage),
+        None => Err(eyre::eyre!(
+            "No saved query named `{name}`. Saved queries: {}",
+            queries.keys().cloned().collect::<Vec<_>>().join(", ")
+        ))
+        .kind(ErrorKind::Usage),
+    }
+}
+
+/// Save `query` as `name`, replacing any query already saved with that name.
+pub fn save(name: &str, query: SavedQuery) -> Result<()> {
+    let mut queries = load()?;
+    queries.insert(name.to_string(), query);
+
+    let path = path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .wrap_err_with(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let text = serde_json::to_string_pretty(&queries)?;
+    std::fs::write(&path, text + "\n")
+        .wrap_err_with(|| format!("Failed to write saved queries to {}", path.display()))
+}
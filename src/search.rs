@@ -0,0 +1,77 @@
+use eyre::{Context, Result};
+use sqlx::sqlite::SqliteConnection;
+
+use crate::db;
+use crate::render::ResultSet;
+
+/// Filters for a keyword search over course titles.
+pub struct SearchOptions<'a> {
+    pub keyword: &'a str,
+    pub subject: Option<&'a str>,
+    pub term: Option<&'a str>,
+    pub open_only: bool,
+}
+
+/// Find sections whose course title contains the keyword (case-insensitive), without the model.
+/// Uses the `sections_fts` index when the database has one, which matches whole words (and word
+/// prefixes) rather than any substring.
+pub async fn search(conn: &mut SqliteConnection, options: &SearchOptions<'_>) -> Result<ResultSet> {
+    let use_fts = db::has_fts(&mut *conn).await?;
+
+    let mut query = String::from(
+        "SELECT s.crn, s.subject, s.number, s.section, s.course_title,
+            (SELECT group_concat(f.name, ', ')
+             FROM course_faculty cf JOIN faculty f ON f.id = cf.faculty_id
+             WHERE cf.course_id = s.id) AS instructor,
+            s.seats_available
+        FROM sections s",
+    );
+    if use_fts {
+        query.push_str(
+            " WHERE s.rowid IN (SELECT rowid FROM sections_fts WHERE sections_fts MATCH ?)",
+        );
+    } else {
+        query.push_str(" WHERE s.course_title LIKE ? ESCAPE '\\'");
+    }
+    if options.subject.is_some() {
+        query.push_str(" AND s.subject = ? COLLATE NOCASE");
+    }
+    if options.term.is_some() {
+        query.push_str(" AND (s.term = ? OR s.term_description = ? COLLATE NOCASE)");
+    }
+    if options.open_only {
+        query.push_str(" AND s.open = 'true'");
+    }
+    query.push_str(" ORDER BY s.term DESC, s.subject, s.number, s.section");
+
+    // Match the keyword literally, even if it contains LIKE wildcards or FTS5 query syntax.
+    let pattern = if use_fts {
+        format!(
+            "course_title : \"{}\" *",
+            options.keyword.replace('"', "\"\"")
+        )
+    } else {
+        let escaped = options
+            .keyword
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        format!("%{escaped}%")
+    };
+
+    let columns = db::column_names(conn, &query).await?;
+    let mut query = sqlx::query(&query).bind(pattern);
+    if let Some(subject) = options.subject {
+        query = query.bind(subject);
+    }
+    if let Some(term) = options.term {
+        query = query.bind(term).bind(term);
+    }
+
+    let rows = query
+        .fetch_all(conn)
+        .await
+        .wrap_err("Failed to search sections")?;
+
+    ResultSet::from_rows(columns, &rows)
+}
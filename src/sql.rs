@@ -0,0 +1,383 @@
+use eyre::Result;
+
+/// Get the SQL out of a model response.
+///
+/// If the response has a markdown code block, only its contents are used, leaving out any prose
+/// around it. A block that's never closed (as when generation stops at its closing fence) runs to
+/// the end. If what's left is a JSON object with a `sql` field (as requested by
+/// `--json-response`), that field is used. Otherwise, it's all taken as SQL.
+pub fn extract_sql(response_text: &str) -> String {
+    let lines: Vec<&str> = response_text.trim().lines().collect();
+    let is_fence = |line: &&str| line.trim().starts_with("```");
+    let text = match lines.iter().position(is_fence) {
+        Some(start) => lines[start + 1..]
+            .iter()
+            .take_while(|line| !is_fence(line))
+            .copied()
+            .collect::<Vec<_>>()
+            .join("\n"),
+        None => lines.join("\n"),
+    };
+
+    #[derive(serde::Deserialize)]
+    struct JsonResponse {
+        sql: String,
+    }
+
+    match serde_json::from_str::<JsonResponse>(&text) {
+        Ok(response) => response.sql,
+        Err(_) => text,
+    }
+}
+
+/// Reduce the model's SQL to a single statement.
+///
+/// Models sometimes emit setup statements before the real query. If there's more than one
+/// statement, warn and keep only the final `SELECT`. A trailing semicolon is fine. Either way,
+/// the statement has to be a `SELECT`.
+pub fn single_statement(sql: &str) -> Result<String> {
+    let statements = split_statements(sql);
+
+    match statements.as_slice() {
+        [] => eyre::bail!("Model returned an empty query"),
+        [statement] if is_select(statement) => Ok(statement.clone()),
+        [_] => eyre::bail!("Model returned a SQL statement that isn't a SELECT:\n{sql}"),
+        _ => {
+            let Some(select) = statements.iter().rev().find(|s| is_select(s)) else {
+                eyre::bail!(
+                    "Model returned {} SQL statements, and none of them is a SELECT:\n{sql}",
+                    statements.len()
+                );
+            };
+
+            eprintln!(
+                "warning: model returned {} SQL statements; running only the final SELECT",
+                statements.len()
+            );
+            Ok(select.clone())
+        }
+    }
+}
+
+/// Whether any statement in `sql` is a `SELECT`, so `single_statement` would find a query in it.
+pub fn has_select(sql: &str) -> bool {
+    split_statements(sql)
+        .iter()
+        .any(|statement| is_select(statement))
+}
+
+/// Lay out a query for reading, with each top-level clause on its own line and its contents
+/// indented below it, and `AND`/`OR` conditions on their own lines. Subqueries are left inline.
+/// Queries with `--` comments are returned as-is, since joining lines would break them.
+pub fn pretty(sql: &str) -> String {
+    const CLAUSES: [&str; 9] = [
+        "SELECT", "FROM", "WHERE", "GROUP BY", "HAVING", "ORDER BY", "LIMIT", "UNION", "WITH",
+    ];
+
+    if sql.contains("--") {
+        return sql.to_string();
+    }
+
+    // Split into words, keeping quoted text intact, and note the paren depth before each word.
+    let mut words: Vec<(String, usize)> = vec![];
+    let mut word = String::new();
+    let mut depth = 0usize;
+    let mut word_depth = 0;
+    let mut chars = sql.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' => {
+                word.push(c);
+                for inner in chars.by_ref() {
+                    word.push(inner);
+                    if inner == c {
+                        break;
+                    }
+                }
+            }
+            c if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push((std::mem::take(&mut word), word_depth));
+                }
+                word_depth = depth;
+            }
+            _ => {
+                if word.is_empty() {
+                    word_depth = depth;
+                }
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                word.push(c);
+            }
+        }
+    }
+    if !word.is_empty() {
+        words.push((word, word_depth));
+    }
+
+    let mut pretty = String::new();
+    let mut i = 0;
+    while i < words.len() {
+        let (word, depth) = &words[i];
+        let two_words = words
+            .get(i + 1)
+            .map(|(next, _)| format!("{word} {next}").to_uppercase());
+
+        let clause = if *depth > 0 {
+            None
+        } else if two_words.as_deref().is_some_and(|w| CLAUSES.contains(&w)) {
+            i += 1;
+            two_words
+        } else if CLAUSES.contains(&word.to_uppercase().as_str()) {
+            Some(word.to_uppercase())
+        } else {
+            None
+        };
+
+        match clause {
+            Some(clause) => {
+                if !pretty.is_empty() {
+                    pretty.push('\n');
+                }
+                pretty.push_str(&clause);
+                pretty.push_str("\n   ");
+            }
+            None if *depth == 0 && matches!(word.to_uppercase().as_str(), "AND" | "OR") => {
+                pretty.push_str("\n    ");
+                pretty.push_str(&word.to_uppercase());
+            }
+            None => {
+                if !pretty.is_empty() {
+                    pretty.push(' ');
+                }
+                pretty.push_str(word);
+            }
+        }
+        i += 1;
+    }
+
+    pretty
+}
+
+/// Cut `sql` down to `max_chars` characters, ending with an ellipsis if anything was removed.
+pub fn truncate(sql: &str, max_chars: usize) -> String {
+    if sql.chars().count() <= max_chars {
+        return sql.to_string();
+    }
+    let mut truncated: String = sql.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Wrap a query to add an `instructor` column with the name of each section's first listed
+/// instructor, joined on the query's `crn` and `term` columns, since CRNs are reused each term.
+pub fn with_instructor(sql: &str) -> String {
+    format!(
+        "SELECT q.*, (
+    SELECT f.name
+    FROM sections s
+    JOIN course_faculty cf ON cf.course_id = s.id
+    JOIN faculty f ON f.id = cf.faculty_id
+    WHERE s.crn = q.crn AND s.term = q.term
+    ORDER BY cf.rowid
+    LIMIT 1
+) AS instructor
+FROM (
+{sql}
+) AS q"
+    )
+}
+
+/// Wrap a query to return only its first `n` rows.
+pub fn first(sql: &str, n: usize) -> String {
+    format!("SELECT * FROM (\n{sql}\n) LIMIT {n}")
+}
+
+/// Wrap a query to drop the rows for online sections, the same ones `ResultSet::exclude_online`
+/// drops, checking whichever of its `campus` and `section` columns it has.
+pub fn exclude_online(sql: &str, campus: bool, section: bool) -> String {
+    let mut conditions = vec![];
+    if campus {
+        conditions.push("\"campus\" IS NOT 'Online'");
+    }
+    if section {
+        conditions.push("coalesce(\"section\", '') NOT GLOB 'O*'");
+    }
+    if conditions.is_empty() {
+        return sql.to_string();
+    }
+    format!(
+        "SELECT * FROM (\n{sql}\n) WHERE {}",
+        conditions.join(" AND ")
+    )
+}
+
+/// Wrap a query to count its rows, in a single `count` column.
+pub fn count(sql: &str) -> String {
+    format!("SELECT COUNT(*) AS count FROM (\n{sql}\n)")
+}
+
+/// Whether a statement is a query (`SELECT ...` or `WITH ... SELECT ...`), after any leading
+/// comments.
+fn is_select(statement: &str) -> bool {
+    let mut rest = statement.trim_start();
+    loop {
+        if let Some(comment) = rest.strip_prefix("--") {
+            rest = comment
+                .split_once('\n')
+                .map_or("", |(_, rest)| rest)
+                .trim_start();
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment
+                .split_once("*/")
+                .map_or("", |(_, rest)| rest)
+                .trim_start();
+        } else {
+            break;
+        }
+    }
+
+    let keyword = rest
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or_default();
+    keyword.eq_ignore_ascii_case("select") || keyword.eq_ignore_ascii_case("with")
+}
+
+/// Split SQL text on `;`, ignoring semicolons inside string literals, quoted identifiers, and
+/// comments. Statements that are empty, or only comments (like one after the final `;`), are
+/// dropped.
+fn split_statements(sql: &str) -> Vec<String> {
+    let mut statements = vec![];
+    let mut current = String::new();
+    let mut has_code = false;
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // Quoted strings and identifiers. Doubled quotes are escapes, which this handles
+            // naturally by closing and re-opening the quote.
+            '\'' | '"' | '`' => {
+                has_code = true;
+                current.push(c);
+                for inner in chars.by_ref() {
+                    current.push(inner);
+                    if inner == c {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                current.push(c);
+                for inner in chars.by_ref() {
+                    current.push(inner);
+                    if inner == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                current.push(c);
+                current.push(chars.next().unwrap());
+                let mut prev = ' ';
+                for inner in chars.by_ref() {
+                    current.push(inner);
+                    if prev == '*' && inner == '/' {
+                        break;
+                    }
+                    prev = inner;
+                }
+            }
+            ';' => {
+                if std::mem::take(&mut has_code) {
+                    statements.push(current.trim().to_string());
+                }
+                current.clear();
+            }
+            _ => {
+                has_code |= !c.is_whitespace();
+                current.push(c);
+            }
+        }
+    }
+    if has_code {
+        statements.push(current.trim().to_string());
+    }
+
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_sql_takes_the_first_code_block() {
+        let response = "Here's a query:\n\n```sql\nSELECT 1\n```\n\nIt selects 1.";
+        assert_eq!(extract_sql(response), "SELECT 1");
+        assert_eq!(extract_sql("```sql\nSELECT 1\nFROM t"), "SELECT 1\nFROM t");
+        assert_eq!(extract_sql("SELECT 1"), "SELECT 1");
+    }
+
+    #[test]
+    fn extract_sql_reads_json_in_a_code_block() {
+        let response = "```json\n{\"sql\": \"SELECT 1\"}\n```";
+        assert_eq!(extract_sql(response), "SELECT 1");
+    }
+
+    #[test]
+    fn prose_cut_off_at_a_code_block_has_no_select() {
+        assert!(!has_select(&extract_sql("Here's a query that finds them:")));
+        assert!(has_select(&extract_sql("```sql\nSELECT 1")));
+    }
+
+    #[test]
+    fn semicolons_in_strings_identifiers_and_comments_dont_split() {
+        let sql = "SELECT 'a;b', \"c;d\" -- e;f\nFROM t /* g;h */";
+        assert_eq!(split_statements(sql), [sql]);
+    }
+
+    #[test]
+    fn a_trailing_semicolon_is_fine() {
+        assert_eq!(split_statements("SELECT 1;"), ["SELECT 1"]);
+        assert_eq!(single_statement("SELECT 1;\n").unwrap(), "SELECT 1");
+    }
+
+    #[test]
+    fn comments_after_the_last_semicolon_arent_a_statement() {
+        assert_eq!(split_statements("SELECT 1; -- done"), ["SELECT 1"]);
+        assert_eq!(split_statements("SELECT 1; /* done */ ;"), ["SELECT 1"]);
+    }
+
+    #[test]
+    fn several_statements_keep_the_last_select() {
+        let sql = "CREATE TEMP TABLE t (x); SELECT 1; SELECT 2; DROP TABLE t";
+        assert_eq!(
+            split_statements(sql),
+            [
+                "CREATE TEMP TABLE t (x)",
+                "SELECT 1",
+                "SELECT 2",
+                "DROP TABLE t"
+            ]
+        );
+        assert_eq!(single_statement(sql).unwrap(), "SELECT 2");
+    }
+
+    #[test]
+    fn statements_must_be_selects() {
+        assert!(single_statement("DELETE FROM sections").is_err());
+        assert!(single_statement("DELETE FROM sections; DROP TABLE faculty").is_err());
+        assert!(single_statement("").is_err());
+        assert!(single_statement("-- nothing").is_err());
+    }
+
+    #[test]
+    fn selects_can_start_with_comments() {
+        let sql = "-- open sections\n/* CS only */ WITH s AS (SELECT 1) SELECT * FROM s";
+        assert_eq!(single_statement(sql).unwrap(), sql);
+    }
+}
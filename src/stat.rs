@@ -0,0 +1,84 @@
+use eyre::{Context, Result};
+use sqlx::sqlite::SqliteConnection;
+
+use crate::db;
+use crate::error::{ErrorKind, WithKind};
+use crate::render::{self, Format, RenderOptions};
+
+/// Summary queries, each printed as its own table under a heading.
+const STATS: &[(&str, &str)] = &[
+    (
+        "Totals",
+        "SELECT
+            (SELECT COUNT(*) FROM sections) AS sections,
+            (SELECT COUNT(*) FROM faculty) AS faculty",
+    ),
+    (
+        "Sections per term",
+        "SELECT term, term_description, COUNT(*) AS sections
+        FROM sections
+        GROUP BY term
+        ORDER BY term DESC",
+    ),
+    (
+        "Open vs. closed",
+        "SELECT open, COUNT(*) AS sections
+        FROM sections
+        GROUP BY open
+        ORDER BY open DESC",
+    ),
+    (
+        "Most popular subjects",
+        "SELECT subject, subject_description, COUNT(*) AS sections
+        FROM sections
+        GROUP BY subject
+        ORDER BY sections DESC
+        LIMIT 10",
+    ),
+];
+
+/// Print summary statistics about the database. Useful to sanity-check a fresh scrape. JSON is a
+/// single object with each summary's rows under its heading.
+pub async fn print(conn: &mut SqliteConnection, options: &RenderOptions) -> Result<()> {
+    let json = matches!(options.format, Format::Json | Format::JsonPretty);
+    let mut sections = vec![];
+    for (i, (heading, query)) in STATS.iter().enumerate() {
+        let results = db::fetch_results(conn, query)
+            .await
+            .wrap_err_with(|| format!("Failed to query {}", heading.to_lowercase()))
+            .kind(ErrorKind::Db)?;
+        let rendered = render::render(&results, heading, query, options)?;
+
+        if json {
+            let separator = if options.format == Format::JsonPretty {
+                ": "
+            } else {
+                ":"
+            };
+            let heading = serde_json::to_string(heading)?;
+            sections.push(format!("{heading}{separator}{rendered}"));
+        } else {
+            // Without the blank line, markdown would read the heading as another table row.
+            if i > 0 {
+                println!();
+            }
+            println!("{heading}:");
+            println!("{rendered}");
+        }
+    }
+
+    if json {
+        match options.format {
+            Format::JsonPretty => {
+                let sections: Vec<String> = sections
+                    .iter()
+                    .map(|section| section.replace('\n', "\n  "))
+                    .collect();
+                println!("{{\n  {}\n}}", sections.join(",\n  "));
+            }
+            _ => println!("{{{}}}", sections.join(",")),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use eyre::{Context, Result};
+use handlebars::template::{HelperTemplate, Parameter, Template, TemplateElement};
+use handlebars::{handlebars_helper, Handlebars, Path};
+use serde_json::Value as Json;
+use unicode_width::UnicodeWidthStr;
+
+use crate::error::{ErrorKind, WithKind};
+use crate::render::{JsonRow, RenderOptions, ResultSet};
+
+/// A handlebars template for each row of the results, like `{{crn}}: {{course_title}}`, with an
+/// optional header and footer.
+pub struct RowTemplate {
+    registry: Handlebars<'static>,
+}
+
+/// Template names in the registry.
+const ROW: &str = "row";
+const HEADER: &str = "header";
+const FOOTER: &str = "footer";
+
+handlebars_helper!(pad: |value: Json, width: u64| pad_text(&text(value), width, false));
+handlebars_helper!(lpad: |value: Json, width: u64| pad_text(&text(value), width, true));
+handlebars_helper!(default: |value: Json, fallback: Json| {
+    match value {
+        Json::Null => fallback.clone(),
+        Json::String(text) if text.is_empty() => fallback.clone(),
+        _ => value.clone(),
+    }
+});
+
+impl RowTemplate {
+    /// Compile the row template and the optional header and footer, which are given the
+    /// `row_count`.
+    pub fn new(row: &str, header: Option<&str>, footer: Option<&str>) -> Result<Self> {
+        let mut registry = Handlebars::new();
+        // Output is text, not HTML, and a misspelled column is an error rather than a blank.
+        registry.register_escape_fn(handlebars::no_escape);
+        registry.set_strict_mode(true);
+        registry.register_helper("pad", Box::new(pad));
+        registry.register_helper("lpad", Box::new(lpad));
+        registry.register_helper("default", Box::new(default));
+
+        let templates = [(ROW, Some(row)), (HEADER, header), (FOOTER, footer)];
+        for (name, source) in templates {
+            if let Some(source) = source {
+                registry
+                    .register_template_string(name, source)
+                    .wrap_err_with(|| format!("Invalid {name} template"))
+                    .kind(ErrorKind::Usage)?;
+            }
+        }
+
+        Ok(RowTemplate { registry })
+    }
+
+    /// Check that the row template only uses these columns, before anything is printed.
+    pub fn check_columns(&self, columns: &[String]) -> Result<()> {
+        let mut names = vec![];
+        if let Some(template) = self.registry.get_template(ROW) {
+            template_variables(template, &mut names);
+        }
+
+        let unknown: Vec<&String> = names
+            .iter()
+            .filter(|name| !columns.contains(name))
+            .collect();
+        if let Some(name) = unknown.first() {
+            return Err(eyre::eyre!(
+                "Unknown column `{name}` in the row template. Available columns: {}",
+                columns.join(", ")
+            ))
+            .kind(ErrorKind::Usage);
+        }
+        Ok(())
+    }
+
+    /// Render the header, each row in `results`, and the footer, one after another on their own
+    /// lines. Boolean columns are booleans, as in JSON output.
+    pub fn render(&self, results: &ResultSet, options: &RenderOptions) -> Result<String> {
+        let bool_columns: &[String] = if options.raw_values {
+            &[]
+        } else {
+            &options.bool_columns
+        };
+        let summary = serde_json::json!({ "row_count": results.rows.len() });
+
+        let mut lines = vec![];
+        if self.registry.has_template(HEADER) {
+            lines.push(self.registry.render(HEADER, &summary)?);
+        }
+        for values in &results.rows {
+            let row = JsonRow {
+                columns: &results.columns,
+                values,
+                bool_columns,
+                raw_json: false,
+            };
+            lines.push(self.registry.render(ROW, &row)?);
+        }
+        if self.registry.has_template(FOOTER) {
+            lines.push(self.registry.render(FOOTER, &summary)?);
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Add the names of the variables `template` uses to `names`.
+fn template_variables(template: &Template, names: &mut Vec<String>) {
+    for element in &template.elements {
+        match element {
+            TemplateElement::Expression(helper)
+            | TemplateElement::HtmlExpression(helper)
+            | TemplateElement::HelperBlock(helper) => helper_variables(helper, names),
+            _ => {}
+        }
+    }
+}
+
+/// Add the names of the variables a helper call, or a bare `{{name}}`, uses to `names`.
+fn helper_variables(helper: &HelperTemplate, names: &mut Vec<String>) {
+    let is_call = helper.block || !helper.params.is_empty() || !helper.hash.is_empty();
+    match &helper.name {
+        Parameter::Name(name) if !is_call => names.push(name.clone()),
+        name => parameter_variables(name, names),
+    }
+    for parameter in helper.params.iter().chain(helper.hash.values()) {
+        parameter_variables(parameter, names);
+    }
+    for template in [&helper.template, &helper.inverse].into_iter().flatten() {
+        template_variables(template, names);
+    }
+}
+
+/// Add the variables a helper's parameter uses to `names`.
+fn parameter_variables(parameter: &Parameter, names: &mut Vec<String>) {
+    match parameter {
+        Parameter::Path(Path::Relative((_, raw))) => {
+            let name = raw.trim_start_matches("this.");
+            if name != "this" {
+                names.push(name.to_string());
+            }
+        }
+        Parameter::Subexpression(subexpression) => {
+            for parameter in subexpression.params().into_iter().flatten() {
+                parameter_variables(parameter, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A JSON value as template text: strings without quotes, and nulls as nothing.
+fn text(value: &Json) -> String {
+    match value {
+        Json::Null => String::new(),
+        Json::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Pad `text` with spaces to `width` columns, on the left if `right_align` is set.
+fn pad_text(text: &str, width: u64, right_align: bool) -> String {
+    let padding = " ".repeat((width as usize).saturating_sub(text.width()));
+    if right_align {
+        padding + text
+    } else {
+        text.to_string() + &padding
+    }
+}
@@ -0,0 +1,60 @@
+use eyre::{Context, Result};
+use sqlx::sqlite::SqliteConnection;
+use std::io::IsTerminal;
+use std::time::Duration;
+
+use crate::agent::{self, Answer};
+use crate::error::{ErrorKind, WithKind};
+use crate::{db, render};
+use crate::{log, Args};
+
+/// Show `answer`, then run its SQL again every `interval` and show the new results in its place,
+/// like `watch(1)`, until Ctrl-C. The model isn't asked again.
+pub async fn run(
+    args: &Args,
+    conn: &mut SqliteConnection,
+    question: &str,
+    answer: Answer,
+    interval: Duration,
+) -> Result<i32> {
+    // Refreshes replace the screen, so there's nothing for a pager to do.
+    let render_options = args.render_options();
+    let clear = std::io::stdout().is_terminal();
+
+    let mut results = answer.results;
+    let mut shown = results.clone();
+    loop {
+        let rendered = render::render(&shown, question, &answer.sql, &render_options)?;
+        if clear {
+            print!("\x1b[2J\x1b[H");
+        }
+        println!(
+            "Every {}s: {question}    {}\n",
+            interval.as_secs(),
+            log::timestamp_now()
+        );
+        println!("{rendered}");
+
+        tokio::time::sleep(interval).await;
+
+        // Ctrl-C exits, as it does everywhere outside a first query.
+        let rows = db::fetch_results(conn, &answer.sql)
+            .await
+            .wrap_err("Failed to execute SQL query")
+            .kind(ErrorKind::Sql)?;
+        let refreshed = agent::prepare_results(args, conn, rows).await?;
+        let previous = std::mem::replace(&mut results, refreshed);
+        // Empty results still have their columns, so a refresh with no rows shows every row as
+        // removed.
+        shown = if args.diff {
+            results
+                .diff(&previous)
+                .ok_or_else(|| {
+                    eyre::eyre!("--diff matches rows by CRN, but the results have no `crn` column")
+                })
+                .kind(ErrorKind::Usage)?
+        } else {
+            results.clone()
+        };
+    }
+}
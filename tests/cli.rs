@@ -0,0 +1,151 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Run `reg-agent` with these arguments, without any OpenAI settings from the environment.
+fn reg_agent(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_reg-agent"))
+        .args(args)
+        .env_remove("OPENAI_API_KEY")
+        .env_remove("GT_REG_QUESTION")
+        .output()
+        .expect("reg-agent runs")
+}
+
+/// A fresh, empty course database made with `init`, named for the test using it.
+fn empty_db(test: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("reg-agent-{test}-{}.sqlite3", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let output = reg_agent(&["--db", path.to_str().unwrap(), "init"]);
+    assert!(output.status.success(), "init failed: {output:?}");
+    path
+}
+
+#[test]
+fn missing_api_key_exits_78() {
+    let output = reg_agent(&["--db", "nonexistent.sqlite3", "open CS classes"]);
+    assert_eq!(output.status.code(), Some(78));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("OPENAI_API_KEY is not set"),
+        "unexpected stderr: {stderr}"
+    );
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn empty_api_key_exits_78() {
+    let output = reg_agent(&["--api-key", "", "open CS classes"]);
+    assert_eq!(output.status.code(), Some(78));
+}
+
+#[test]
+fn sql_without_the_model_needs_no_api_key() {
+    // Gets as far as opening the database, which doesn't exist.
+    let output = reg_agent(&["--db", "nonexistent.sqlite3", "--sql", "SELECT 1"]);
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn bad_sql_exits_5() {
+    let db = empty_db("bad-sql");
+    let db_arg = db.to_str().unwrap();
+
+    // Caught before it's run, since it isn't a SELECT.
+    let output = reg_agent(&["--db", db_arg, "--sql", "SELEC nope"]);
+    assert_eq!(output.status.code(), Some(5));
+
+    // Caught by SQLite as it runs.
+    let output = reg_agent(&["--db", db_arg, "--sql", "SELECT json('nope')"]);
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("malformed JSON"),
+        "unexpected stderr: {stderr}"
+    );
+
+    let _ = std::fs::remove_file(&db);
+}
+
+#[test]
+fn markdown_without_a_header_is_a_usage_error() {
+    let output = reg_agent(&["--format", "markdown", "--no-header", "--sql", "SELECT 1"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Markdown tables need a header row"),
+        "unexpected stderr: {stderr}"
+    );
+}
+
+#[test]
+fn export_has_its_own_format() {
+    let db = empty_db("export");
+    let db = db.to_str().unwrap();
+
+    let output = reg_agent(&["--db", db, "export", "--table", "faculty"]);
+    assert!(output.status.success(), "export failed: {output:?}");
+
+    let output = reg_agent(&[
+        "--db", db, "export", "--table", "faculty", "--format", "json",
+    ]);
+    assert!(output.status.success(), "export failed: {output:?}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "[]");
+    let _ = std::fs::remove_file(db);
+}
+
+#[test]
+fn batch_results_cant_be_tables() {
+    let db = empty_db("batch");
+    let dir = std::env::temp_dir().join(format!("reg-agent-batch-{}", std::process::id()));
+    let questions = dir.with_extension("csv");
+    std::fs::write(&questions, "question\nopen CS classes\n").unwrap();
+
+    let output = reg_agent(&[
+        "--db",
+        db.to_str().unwrap(),
+        "--api-key",
+        "unused",
+        "--csv-input",
+        questions.to_str().unwrap(),
+        "--output-dir",
+        dir.to_str().unwrap(),
+        "--format",
+        "table",
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("can't use --format table"),
+        "unexpected stderr: {stderr}"
+    );
+    assert!(!dir.exists());
+
+    let _ = std::fs::remove_file(&questions);
+    let _ = std::fs::remove_file(&db);
+}
+
+#[test]
+fn crns_past_the_limit_are_noted() {
+    let db = empty_db("crns");
+    let sql = "SELECT '10001' AS crn UNION ALL SELECT '10002' UNION ALL SELECT '10003'";
+    let args = ["--db", db.to_str().unwrap(), "--sql", sql, "--crns-only"];
+
+    let output = reg_agent(&[&args[..], &["--limit", "2"]].concat());
+    assert!(output.status.success(), "--crns-only failed: {output:?}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "10001\n10002\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("and 1 more CRN (use --limit 0 for all)"),
+        "unexpected stderr: {stderr}"
+    );
+
+    let output = reg_agent(&[&args[..], &["--limit", "0"]].concat());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "10001\n10002\n10003\n"
+    );
+    assert!(output.stderr.is_empty());
+
+    let _ = std::fs::remove_file(&db);
+}